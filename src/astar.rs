@@ -0,0 +1,210 @@
+use std::cmp::Reverse;
+use std::collections::hash_map::Entry;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::game::{EndState, State};
+use crate::search::{Meter, SearchBudget, SearchObserver, SearchOutcome, SearchResult, Tracker};
+
+struct Node<S: State> {
+    state: S,
+    parent: Option<usize>,
+    action: Option<<S as State>::Action>,
+    cost: usize,
+}
+
+/// Best-first search ordered by `g + h`, where `g` is the number of moves made so far and `h` is
+/// the game's `cost_to_go` estimate.
+/// As long as `cost_to_go` never overestimates, the first win popped from the open list is a
+/// shortest win path.
+/// If the open list runs out, every reachable state has been searched, so the deal is unsolvable.
+pub fn astar<S: State + Clone>(
+    state: S,
+    budget: &SearchBudget,
+    observer: &mut dyn SearchObserver,
+) -> SearchResult<<S as State>::Action> {
+    let mut tracker = Tracker::new(budget, observer);
+
+    // All discovered nodes, so that paths can be rebuilt by following parent indices
+    let mut nodes = vec![Node {
+        state: state.clone(),
+        parent: None,
+        action: None,
+        cost: 0,
+    }];
+    // Ordered by lowest `g + h`, then by highest `g` to prefer nodes closer to a win
+    let mut open = BinaryHeap::new();
+    open.push((Reverse(state.cost_to_go()), 0, Reverse(0)));
+    // Lowest cost we've found to reach each state
+    let mut best_costs = HashMap::new();
+    best_costs.insert(state, 0);
+
+    while let Some((_, cost, Reverse(idx))) = open.pop() {
+        if let Some(reason) = tracker.out_of_budget() {
+            return tracker.finish(SearchOutcome::Inconclusive { reason });
+        }
+
+        let state = &nodes[idx].state;
+        // Skip stale entries that were superseded by a cheaper path to the same state
+        if best_costs.get(state).is_some_and(|&best| best < cost) {
+            continue;
+        }
+
+        match state.end_status() {
+            Some(EndState::Win) => {
+                return tracker.finish(SearchOutcome::Solved(rebuild_path(nodes, idx)))
+            }
+            Some(EndState::Loss) => continue,
+            None => (),
+        }
+
+        tracker.expand(cost);
+        for mv in state.actions_after(nodes[idx].action.as_ref()) {
+            let next_state = nodes[idx].state.act(&mv);
+            tracker.generate();
+            let next_cost = cost + 1;
+            match best_costs.entry(next_state.clone()) {
+                Entry::Occupied(mut entry) => {
+                    if *entry.get() <= next_cost {
+                        tracker.prune_duplicate();
+                        continue;
+                    }
+                    entry.insert(next_cost);
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(next_cost);
+                }
+            }
+
+            let next_idx = nodes.len();
+            open.push((
+                Reverse(next_cost + next_state.cost_to_go()),
+                next_cost,
+                Reverse(next_idx),
+            ));
+            nodes.push(Node {
+                state: next_state,
+                parent: Some(idx),
+                action: Some(mv),
+                cost: next_cost,
+            });
+        }
+    }
+
+    tracker.finish(SearchOutcome::ProvenUnsolvable {
+        states_explored: best_costs.len(),
+    })
+}
+
+fn rebuild_path<S: State>(mut nodes: Vec<Node<S>>, idx: usize) -> Vec<<S as State>::Action> {
+    let mut path = Vec::with_capacity(nodes[idx].cost);
+    let mut current = Some(idx);
+    while let Some(idx) = current {
+        let node = &mut nodes[idx];
+        if let Some(action) = node.action.take() {
+            path.push(action);
+        }
+        current = node.parent;
+    }
+    path.reverse();
+
+    path
+}
+
+#[cfg(test)]
+mod test {
+    use crate::astar::astar;
+    use crate::game::Card;
+    use crate::game::Rank::Six;
+    use crate::game::Suit::Heart;
+    use crate::game::State;
+    use crate::russian::Move;
+    use crate::search::{SearchBudget, SearchOutcome, StopReason};
+    use crate::testing::{nearly_solved_russian, nearly_sorted_fortune, DeadEnd, NumberLine};
+
+    #[test]
+    fn test_shortest_path() {
+        let path = astar(NumberLine(0), &SearchBudget::default(), &mut ())
+            .outcome
+            .into_path()
+            .expect("Number line should be solvable");
+        assert_eq!(path.len(), 4);
+        assert_eq!(path.iter().sum::<i32>(), 10);
+    }
+
+    #[test]
+    fn test_already_won() {
+        assert_eq!(
+            astar(NumberLine(10), &SearchBudget::default(), &mut ()).outcome,
+            SearchOutcome::Solved(vec![])
+        );
+    }
+
+    #[test]
+    fn test_russian_shortest_path() {
+        let mut state = nearly_solved_russian();
+        let path = astar(state.clone(), &SearchBudget::default(), &mut ())
+            .outcome
+            .into_path()
+            .expect("Deal should be solvable");
+        assert_eq!(path.len(), 2);
+        assert!(path.contains(&Move::Stack {
+            cards: vec![Card {
+                rank: Six,
+                suit: Heart
+            }],
+            from: 8,
+            to: 2,
+        }));
+
+        for mv in &path {
+            state = state.act(mv);
+        }
+        assert!(state.is_win());
+    }
+
+    #[test]
+    fn test_fortune_shortest_path() {
+        let mut state = nearly_sorted_fortune();
+        let path = astar(state.clone(), &SearchBudget::default(), &mut ())
+            .outcome
+            .into_path()
+            .expect("Deal should be solvable");
+        assert_eq!(path.len(), state.cost_to_go());
+
+        for mv in &path {
+            state = state.act(mv);
+        }
+        assert!(state.is_win());
+    }
+
+    #[test]
+    fn test_unsolvable() {
+        let result = astar(DeadEnd(0), &SearchBudget::default(), &mut ());
+        assert_eq!(
+            result.outcome,
+            SearchOutcome::ProvenUnsolvable { states_explored: 7 }
+        );
+        // Every state below 5 is expanded, and 4 of the 10 moves lead to a state
+        // that was already reached in as few moves
+        assert_eq!(result.stats.nodes_expanded, 5);
+        assert_eq!(result.stats.nodes_generated, 10);
+        assert_eq!(result.stats.duplicates_pruned, 4);
+        assert_eq!(result.stats.max_depth, 2);
+    }
+
+    #[test]
+    fn test_node_limit() {
+        let budget = SearchBudget {
+            max_nodes: Some(3),
+            ..SearchBudget::default()
+        };
+        let result = astar(DeadEnd(0), &budget, &mut ());
+        assert_eq!(
+            result.outcome,
+            SearchOutcome::Inconclusive {
+                reason: StopReason::MaxNodes
+            }
+        );
+        assert_eq!(result.stats.nodes_expanded, 3);
+    }
+}
@@ -0,0 +1,311 @@
+use std::collections::HashSet;
+
+use rand::prelude::SliceRandom;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::game::{EndState, State};
+use crate::search::{
+    Meter, SearchBudget, SearchObserver, SearchOutcome, SearchStats, StopReason, Tracker,
+};
+
+/// How to order states that `evaluate` scores the same
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TieBreak {
+    /// Keep the order the moves were generated in
+    Generated,
+    /// Prefer states with a lower `cost_to_go`
+    CostToGo,
+    /// Shuffle tied states with a seeded RNG
+    Random { seed: u64 },
+}
+
+#[derive(Clone, Debug)]
+pub struct BeamConfig {
+    /// Number of states kept at each depth. A width of 0 is treated as 1.
+    pub width: usize,
+    pub max_depth: Option<usize>,
+    pub budget: SearchBudget,
+    pub tie_break: TieBreak,
+}
+
+impl Default for BeamConfig {
+    fn default() -> Self {
+        Self {
+            width: 100,
+            max_depth: None,
+            budget: SearchBudget::default(),
+            tie_break: TieBreak::Generated,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BeamResult<A> {
+    /// The winning line if one was found, otherwise the line to the best scoring state
+    pub path: Vec<A>,
+    pub score: f32,
+    /// Only proven unsolvable if no state was ever dropped from the beam
+    pub outcome: SearchOutcome<A>,
+    pub stats: SearchStats,
+}
+
+struct Candidate<S: State> {
+    state: S,
+    path: Vec<<S as State>::Action>,
+    score: f32,
+    cost_to_go: usize,
+}
+
+/// Breadth-first search that only keeps the `width` best states (by `evaluate`) at each depth.
+/// States already seen at a shallower depth are dropped.
+/// Returns the first win found, or the best partial line if the search runs out of states,
+/// depth or budget.
+pub fn beam_search<S: State + Clone>(
+    state: S,
+    config: &BeamConfig,
+    observer: &mut dyn SearchObserver,
+) -> BeamResult<<S as State>::Action> {
+    let max_depth = config.max_depth.unwrap_or(usize::MAX);
+    let mut tracker = Tracker::new(&config.budget, observer);
+    let mut rng = match config.tie_break {
+        TieBreak::Random { seed } => Some(StdRng::seed_from_u64(seed)),
+        _ => None,
+    };
+
+    // The outcome and statistics are filled in by `finish` once the search ends
+    let mut best = BeamResult {
+        path: vec![],
+        score: state.evaluate(false),
+        outcome: SearchOutcome::Solved(vec![]),
+        stats: SearchStats::default(),
+    };
+    match state.end_status() {
+        Some(EndState::Win) => return finish(best, SearchOutcome::Solved(vec![]), tracker),
+        Some(EndState::Loss) => {
+            let outcome = SearchOutcome::ProvenUnsolvable { states_explored: 1 };
+            return finish(best, outcome, tracker);
+        }
+        None => (),
+    }
+
+    let mut seen = HashSet::new();
+    seen.insert(state.clone());
+    let mut beam = vec![(state, vec![])];
+    let mut dropped_states = false;
+    for depth in 0..max_depth {
+        let mut candidates = Vec::new();
+        for (state, path) in &beam {
+            if let Some(reason) = tracker.out_of_budget() {
+                return finish(best, SearchOutcome::Inconclusive { reason }, tracker);
+            }
+            tracker.expand(depth);
+            for mv in state.actions_after(path.last()) {
+                let next_state = state.act(&mv);
+                tracker.generate();
+                if !seen.insert(next_state.clone()) {
+                    tracker.prune_duplicate();
+                    continue;
+                }
+
+                let mut path = path.clone();
+                path.push(mv);
+                match next_state.end_status() {
+                    Some(EndState::Win) => {
+                        best.score = next_state.evaluate(false);
+                        best.path.clone_from(&path);
+                        return finish(best, SearchOutcome::Solved(path), tracker);
+                    }
+                    Some(EndState::Loss) => continue,
+                    None => (),
+                }
+
+                candidates.push(Candidate {
+                    score: next_state.evaluate(false),
+                    cost_to_go: next_state.cost_to_go(),
+                    state: next_state,
+                    path,
+                });
+            }
+        }
+        if candidates.is_empty() {
+            let outcome = if dropped_states {
+                SearchOutcome::Inconclusive {
+                    reason: StopReason::NotExhaustive,
+                }
+            } else {
+                SearchOutcome::ProvenUnsolvable {
+                    states_explored: seen.len(),
+                }
+            };
+            return finish(best, outcome, tracker);
+        }
+
+        // Sorts are stable, so shuffling first randomizes the order of tied states
+        if let Some(rng) = rng.as_mut() {
+            candidates.shuffle(rng);
+        }
+        candidates.sort_by(|a, b| {
+            let ordering = b.score.total_cmp(&a.score);
+            match config.tie_break {
+                TieBreak::CostToGo => ordering.then(a.cost_to_go.cmp(&b.cost_to_go)),
+                TieBreak::Generated | TieBreak::Random { .. } => ordering,
+            }
+        });
+        // A width of 0 would leave nothing to search, so the beam always keeps the best state
+        let width = config.width.max(1);
+        dropped_states |= candidates.len() > width;
+        candidates.truncate(width);
+
+        if candidates[0].score > best.score {
+            best.score = candidates[0].score;
+            best.path.clone_from(&candidates[0].path);
+        }
+        beam = candidates
+            .into_iter()
+            .map(|candidate| (candidate.state, candidate.path))
+            .collect();
+    }
+
+    // Every depth up to the limit still had states to search
+    let outcome = SearchOutcome::Inconclusive {
+        reason: StopReason::MaxDepth,
+    };
+    finish(best, outcome, tracker)
+}
+
+/// Fills in how the search ended, along with its final statistics
+fn finish<A>(
+    mut best: BeamResult<A>,
+    outcome: SearchOutcome<A>,
+    tracker: Tracker,
+) -> BeamResult<A> {
+    let result = tracker.finish(outcome);
+    best.outcome = result.outcome;
+    best.stats = result.stats;
+    best
+}
+
+#[cfg(test)]
+mod test {
+    use crate::beam::{beam_search, BeamConfig, TieBreak};
+    use crate::game::State;
+    use crate::search::{SearchBudget, SearchOutcome, StopReason};
+    use crate::testing::{nearly_solved_russian, DeadEnd, NumberLine};
+
+    #[test]
+    fn test_finds_win() {
+        let config = BeamConfig {
+            width: 1,
+            ..BeamConfig::default()
+        };
+        let result = beam_search(NumberLine(0), &config, &mut ());
+
+        // The greedy line overshoots from 9, so it has to finish with a +1
+        assert!(result.outcome.is_solved());
+        assert_eq!(result.path, vec![3, 3, 3, 1]);
+    }
+
+    #[test]
+    fn test_zero_width() {
+        // Searched like a width of 1 rather than panicking on an empty beam
+        let config = BeamConfig {
+            width: 0,
+            ..BeamConfig::default()
+        };
+        let result = beam_search(NumberLine(0), &config, &mut ());
+        assert!(result.outcome.is_solved());
+        assert_eq!(result.path, vec![3, 3, 3, 1]);
+    }
+
+    #[test]
+    fn test_partial_line() {
+        let config = BeamConfig {
+            width: 2,
+            max_depth: Some(2),
+            ..BeamConfig::default()
+        };
+        let result = beam_search(NumberLine(0), &config, &mut ());
+
+        assert_eq!(
+            result.outcome,
+            SearchOutcome::Inconclusive {
+                reason: StopReason::MaxDepth
+            }
+        );
+        assert_eq!(result.path, vec![3, 3]);
+        assert_eq!(result.score, 6.);
+    }
+
+    #[test]
+    fn test_budget() {
+        // Enough to expand the root and both states at depth 1, but not the whole of depth 2
+        let config = BeamConfig {
+            width: 2,
+            budget: SearchBudget {
+                max_nodes: Some(4),
+                ..SearchBudget::default()
+            },
+            ..BeamConfig::default()
+        };
+        let result = beam_search(NumberLine(0), &config, &mut ());
+
+        assert_eq!(
+            result.outcome,
+            SearchOutcome::Inconclusive {
+                reason: StopReason::MaxNodes
+            }
+        );
+        assert_eq!(result.path, vec![3, 3]);
+        assert_eq!(result.stats.nodes_expanded, 4);
+    }
+
+    #[test]
+    fn test_russian() {
+        for tie_break in [
+            TieBreak::Generated,
+            TieBreak::CostToGo,
+            TieBreak::Random { seed: 1 },
+        ] {
+            let config = BeamConfig {
+                width: 10,
+                tie_break,
+                ..BeamConfig::default()
+            };
+            let mut state = nearly_solved_russian();
+            let result = beam_search(state.clone(), &config, &mut ());
+            assert!(result.outcome.is_solved());
+
+            for mv in &result.path {
+                state = state.act(mv);
+            }
+            assert!(state.is_win());
+        }
+    }
+
+    #[test]
+    fn test_unsolvable() {
+        // A beam wide enough to keep every state searches them all
+        let config = BeamConfig {
+            width: 10,
+            ..BeamConfig::default()
+        };
+        let result = beam_search(DeadEnd(0), &config, &mut ());
+        assert_eq!(
+            result.outcome,
+            SearchOutcome::ProvenUnsolvable { states_explored: 7 }
+        );
+
+        let config = BeamConfig {
+            width: 1,
+            ..BeamConfig::default()
+        };
+        let result = beam_search(DeadEnd(0), &config, &mut ());
+        assert_eq!(
+            result.outcome,
+            SearchOutcome::Inconclusive {
+                reason: StopReason::NotExhaustive
+            }
+        );
+    }
+}
@@ -0,0 +1,91 @@
+use std::time::{Duration, Instant};
+
+use crate::game::{Reversible, State};
+use crate::minimax::{best_move, best_move_in_place};
+use crate::search::SearchBudget;
+
+/// Counts the states reachable in exactly `depth` moves, creating a new state for every move
+pub fn count_nodes<S: State>(state: &S, depth: usize) -> usize {
+    if depth == 0 || state.end_status().is_some() {
+        return 1;
+    }
+
+    state
+        .possible_actions()
+        .iter()
+        .map(|mv| count_nodes(&state.act(mv), depth - 1))
+        .sum()
+}
+
+/// Same as `count_nodes`, but applies and undoes moves on a single state
+pub fn count_nodes_in_place<S: Reversible>(state: &mut S, depth: usize) -> usize {
+    if depth == 0 || state.end_status().is_some() {
+        return 1;
+    }
+
+    let mut count = 0;
+    for mv in state.possible_actions() {
+        let undo = state.apply_in_place(&mv);
+        count += count_nodes_in_place(state, depth - 1);
+        state.undo(undo);
+    }
+
+    count
+}
+
+fn time<T>(f: impl FnOnce() -> T) -> (T, Duration) {
+    let start = Instant::now();
+    let result = f();
+    (result, start.elapsed())
+}
+
+/// Times walking the tree and a minimax search to `depth`, with and without `Reversible`
+pub fn compare<S: Reversible + Clone>(name: &str, state: S, depth: usize) {
+    let (nodes, act_time) = time(|| count_nodes(&state, depth));
+    let (in_place_nodes, in_place_time) = time(|| count_nodes_in_place(&mut state.clone(), depth));
+    assert_eq!(
+        nodes, in_place_nodes,
+        "Both walks should visit the same states"
+    );
+    println!(
+        "{name:<12} walk depth {depth}: {nodes:>9} nodes  act {:>8.2}ms  in place {:>8.2}ms  ({:.2}x)",
+        act_time.as_secs_f64() * 1000.,
+        in_place_time.as_secs_f64() * 1000.,
+        act_time.as_secs_f64() / in_place_time.as_secs_f64()
+    );
+
+    let budget = SearchBudget::default();
+    let (_, act_time) = time(|| best_move(&state, Some(depth), &budget));
+    let (_, in_place_time) = time(|| best_move_in_place(&mut state.clone(), Some(depth), &budget));
+    println!(
+        "{name:<12} minimax depth {depth}:              act {:>8.2}ms  in place {:>8.2}ms  ({:.2}x)",
+        act_time.as_secs_f64() * 1000.,
+        in_place_time.as_secs_f64() * 1000.,
+        act_time.as_secs_f64() / in_place_time.as_secs_f64()
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bench::{count_nodes, count_nodes_in_place};
+    use crate::states::{fortune_6, russian_3, state_0001};
+
+    #[test]
+    fn test_in_place_walk_matches() {
+        let state = state_0001();
+        assert_eq!(
+            count_nodes(&state, 6),
+            count_nodes_in_place(&mut state.clone(), 6)
+        );
+        let state = russian_3();
+        assert_eq!(
+            count_nodes(&state, 2),
+            count_nodes_in_place(&mut state.clone(), 2)
+        );
+        let state = fortune_6();
+        assert_eq!(
+            count_nodes(&state, 3),
+            count_nodes_in_place(&mut state.clone(), 3)
+        );
+    }
+}
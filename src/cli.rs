@@ -0,0 +1,1160 @@
+use std::error::Error;
+use std::fmt::{Debug, Display};
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use clap::{Args, Parser, Subcommand};
+use rand::random;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::astar::astar;
+use crate::beam::{beam_search, BeamConfig, TieBreak};
+use crate::bench::compare;
+use crate::cribbage::CribbageSolitaire;
+use crate::dfs::{dfs_in_place, dfs_r, optimal_dfs};
+use crate::fortune::FortunesFoundation;
+use crate::game::{display_moves, Game, ParseError, Reversible, SearchPath, State};
+use crate::idastar::idastar;
+use crate::mcts::{mcts_move, MctsConfig, Rollout};
+use crate::minimax::best_move;
+use crate::parallel::{parallel_best_move, parallel_dfs};
+use crate::russian::ProletariatsPatience;
+use crate::search::{SearchBudget, SearchObserver, SearchOutcome, SearchStats, StopReason};
+use crate::solution::Solution;
+use crate::solver::Solver;
+use crate::states::{
+    fortune_6, russian_3, state_0001, Presets, CRIBBAGE_PRESETS, FORTUNE_PRESETS, RUSSIAN_PRESETS,
+};
+use crate::survey::{DealReport, Outcome, Survey};
+
+#[derive(Parser)]
+#[command(about = "Solves and plays solitaire games")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Searches for a solution to a deal
+    Solve {
+        #[command(flatten)]
+        deal: DealArgs,
+        #[arg(short, long, value_enum, default_value_t = Solver::Idastar)]
+        solver: Solver,
+        #[command(flatten)]
+        limits: Limits,
+        /// Step through the solution one move at a time instead of printing it
+        #[arg(long)]
+        step: bool,
+        /// Also save the solution as JSON, so it can be replayed later
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Plays a deal interactively, with the solver suggesting a move each turn
+    Play {
+        #[command(flatten)]
+        deal: DealArgs,
+        #[command(flatten)]
+        limits: Limits,
+    },
+    /// Checks a solution saved by `solve --output` and shows the positions it goes through
+    Replay {
+        /// Solution file, or `-` for stdin
+        solution: PathBuf,
+        /// Step through the positions one move at a time instead of printing them all
+        #[arg(long)]
+        step: bool,
+    },
+    /// Compares searching with cloned states against searching in place on the presets
+    Bench {
+        /// Only benchmark this game
+        #[arg(short, long, value_enum)]
+        game: Option<Game>,
+        /// Search depth, defaults to a depth that takes a few seconds for each game
+        #[arg(short, long)]
+        depth: Option<usize>,
+    },
+    /// Solves a range of seeded deals and reports how many of them can be won
+    Survey {
+        #[arg(short, long, value_enum, default_value_t = Game::Russian)]
+        game: Game,
+        #[arg(short, long, value_enum, default_value_t = Solver::Idastar)]
+        solver: Solver,
+        /// Number of deals to solve
+        #[arg(short = 'n', long, default_value_t = 100)]
+        deals: u64,
+        /// Seed of the first deal, the others use the seeds after it
+        #[arg(long, default_value_t = 0)]
+        first_seed: u64,
+        /// Limits for each deal
+        #[command(flatten)]
+        limits: Limits,
+        /// Save the result of each deal as CSV
+        #[arg(long)]
+        csv: Option<PathBuf>,
+        /// Save the result of each deal as JSON
+        #[arg(long)]
+        json: Option<PathBuf>,
+    },
+    /// Prints a deal as JSON that can be read back with `--input`
+    Generate {
+        #[arg(short, long, value_enum)]
+        game: Game,
+        /// Deal number, a random one is picked if this isn't given
+        #[arg(long)]
+        seed: Option<u64>,
+        /// Print the deal in the game's own text layout instead of JSON
+        #[arg(long)]
+        layout: bool,
+    },
+}
+
+#[derive(Args)]
+pub struct DealArgs {
+    #[arg(short, long, value_enum, default_value_t = Game::Fortune)]
+    game: Game,
+    /// File to read the deal from, or `-` for stdin. Either JSON or the layout the game prints.
+    #[arg(short, long, conflicts_with = "preset")]
+    input: Option<PathBuf>,
+    /// Built-in deal from `states.rs`, like `fortune_6` or `russian_3`
+    #[arg(short, long)]
+    preset: Option<String>,
+    /// Deal number, the same number always gives the same deal.
+    /// A random deal is used if none of `--input`, `--preset` or `--seed` are given.
+    #[arg(long, conflicts_with_all = ["input", "preset"])]
+    seed: Option<u64>,
+}
+
+impl DealArgs {
+    /// Seed to deal with, picking a random one if none was given
+    fn seed(&self) -> u64 {
+        seed_or_random(self.seed)
+    }
+}
+
+#[derive(Args)]
+pub struct Limits {
+    /// Maximum search depth in moves.
+    /// The solvers that pick one move at a time default to 15.
+    #[arg(short, long)]
+    depth: Option<usize>,
+    /// Maximum search time in seconds for a whole deal. The solvers that pick one move at a time
+    /// also take at most 10 seconds per move, except in `play`, where this is the time per move.
+    #[arg(short, long, value_parser = parse_seconds)]
+    time: Option<Duration>,
+    /// Worker threads for the parallel solvers, defaults to one per core
+    #[arg(long)]
+    threads: Option<usize>,
+    /// Maximum number of states to expand (per move for the solvers that pick one move at a time)
+    #[arg(long)]
+    max_nodes: Option<u64>,
+    /// Stop searching once the search has allocated this much memory, in MB
+    #[arg(long)]
+    max_memory: Option<usize>,
+    /// Most memory the IDA* transposition table may grow to, in MB
+    #[arg(long, default_value_t = 64)]
+    memory: usize,
+    /// Number of states kept at each depth of a beam search
+    #[arg(long, default_value_t = 1000, value_parser = parse_width)]
+    width: usize,
+    /// How beam search orders states with the same score: `generated`, `cost-to-go`,
+    /// or `random:<seed>`
+    #[arg(long, default_value = "cost-to-go", value_parser = parse_tie_break)]
+    tie_break: TieBreak,
+    /// MCTS iterations per move
+    #[arg(long, default_value_t = 10_000)]
+    iterations: usize,
+}
+
+fn parse_tie_break(input: &str) -> Result<TieBreak, String> {
+    match input.split_once(':') {
+        None if input == "generated" => Ok(TieBreak::Generated),
+        None if input == "cost-to-go" => Ok(TieBreak::CostToGo),
+        Some(("random", seed)) => seed
+            .parse()
+            .map(|seed| TieBreak::Random { seed })
+            .map_err(|_| format!("invalid seed {seed}")),
+        _ => Err("expected generated, cost-to-go or random:<seed>".to_owned()),
+    }
+}
+
+fn parse_width(input: &str) -> Result<usize, String> {
+    match input.parse() {
+        Ok(0) => Err("a beam has to keep at least one state".to_owned()),
+        Ok(width) => Ok(width),
+        Err(_) => Err(format!("expected a number of states, found {input}")),
+    }
+}
+
+fn parse_seconds(input: &str) -> Result<Duration, String> {
+    let seconds = input
+        .parse()
+        .map_err(|_| format!("expected a number of seconds, found {input}"))?;
+    Duration::try_from_secs_f64(seconds).map_err(|_| format!("{input} isn't a valid time"))
+}
+
+/// Limits for the solvers that pick one move at a time when none are given, since searching
+/// without any limit would never return
+const MOVE_DEPTH: usize = 15;
+const MOVE_TIME: Duration = Duration::from_secs(10);
+
+impl Limits {
+    /// Budget for a search starting now
+    fn budget(&self) -> SearchBudget {
+        self.budget_for(self.time)
+    }
+
+    fn budget_for(&self, time: Option<Duration>) -> SearchBudget {
+        SearchBudget {
+            max_nodes: self.max_nodes,
+            max_memory: self.max_memory.map(|mb| mb * 1024 * 1024),
+            ..SearchBudget::time_limit(time)
+        }
+    }
+
+    fn move_depth(&self) -> Option<usize> {
+        self.depth.or(Some(MOVE_DEPTH))
+    }
+
+    /// Budget for picking a single move starting now
+    fn move_budget(&self) -> SearchBudget {
+        self.budget_for(Some(self.time.unwrap_or(MOVE_TIME)))
+    }
+
+    /// Budget for picking the next move of a line that has to be finished by `deadline`
+    fn line_move_budget(&self, deadline: Option<Instant>) -> SearchBudget {
+        let budget = self.budget_for(Some(MOVE_TIME));
+        SearchBudget {
+            deadline: match (budget.deadline, deadline) {
+                (Some(move_deadline), Some(deadline)) => Some(move_deadline.min(deadline)),
+                (move_deadline, deadline) => move_deadline.or(deadline),
+            },
+            ..budget
+        }
+    }
+}
+
+pub fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    match cli.command {
+        Command::Solve {
+            deal,
+            solver,
+            limits,
+            step,
+            output,
+        } => {
+            let output = output.as_deref().map(|path| (path, deal.game));
+            match deal.game {
+                Game::Fortune => solve(fortune_deal(&deal)?, solver, &limits, step, output),
+                Game::Russian => solve(russian_deal(&deal)?, solver, &limits, step, output),
+                Game::Cribbage => solve(cribbage_deal(&deal)?, solver, &limits, step, output),
+            }
+        }
+        Command::Play { deal, limits } => match deal.game {
+            Game::Fortune => play(fortune_deal(&deal)?, &limits),
+            Game::Russian => play(russian_deal(&deal)?, &limits),
+            Game::Cribbage => play(cribbage_deal(&deal)?, &limits),
+        },
+        Command::Replay { solution, step } => {
+            // The game decides how to read the rest of the document
+            let solution: serde_json::Value = serde_json::from_str(&read_input(&solution)?)?;
+            let game = Game::deserialize(&solution["game"])
+                .map_err(|err| format!("Can't tell which game the solution is for: {err}"))?;
+            match game {
+                Game::Fortune => replay::<FortunesFoundation>(solution, step),
+                Game::Russian => replay::<ProletariatsPatience>(solution, step),
+                Game::Cribbage => replay::<CribbageSolitaire>(solution, step),
+            }
+        }
+        Command::Bench { game, depth } => {
+            if game.is_none_or(|game| game == Game::Cribbage) {
+                compare("cribbage", state_0001(), depth.unwrap_or(8));
+            }
+            if game.is_none_or(|game| game == Game::Russian) {
+                compare("russian", russian_3(), depth.unwrap_or(3));
+            }
+            if game.is_none_or(|game| game == Game::Fortune) {
+                compare("fortune", fortune_6(), depth.unwrap_or(4));
+            }
+            Ok(())
+        }
+        Command::Survey {
+            game,
+            solver,
+            deals,
+            first_seed,
+            limits,
+            csv,
+            json,
+        } => {
+            let seeds = first_seed..first_seed.saturating_add(deals);
+            let survey = match game {
+                Game::Fortune => survey(FortunesFoundation::deal, game, solver, seeds, &limits),
+                Game::Russian => survey(ProletariatsPatience::deal, game, solver, seeds, &limits),
+                Game::Cribbage => survey(CribbageSolitaire::deal, game, solver, seeds, &limits),
+            };
+            println!("\n{}", survey.summary());
+            if let Some(path) = csv {
+                fs::write(path, survey.to_csv())?;
+            }
+            if let Some(path) = json {
+                fs::write(path, serde_json::to_string_pretty(&survey)?)?;
+            }
+            Ok(())
+        }
+        Command::Generate { game, seed, layout } => {
+            let seed = seed_or_random(seed);
+            match (game, layout) {
+                (Game::Fortune, false) => print_json(&FortunesFoundation::deal(seed)),
+                (Game::Fortune, true) => {
+                    print!("{}", FortunesFoundation::deal(seed).to_layout());
+                    Ok(())
+                }
+                (Game::Russian, false) => print_json(&ProletariatsPatience::deal(seed)),
+                (Game::Russian, true) => {
+                    println!("{}", ProletariatsPatience::deal(seed));
+                    Ok(())
+                }
+                (Game::Cribbage, false) => print_json(&CribbageSolitaire::deal(seed)),
+                (Game::Cribbage, true) => {
+                    println!("{}", CribbageSolitaire::deal(seed));
+                    Ok(())
+                }
+            }
+        }
+    }
+}
+
+/// Picks a random seed if none was given, printing it so the deal can be played again
+fn seed_or_random(seed: Option<u64>) -> u64 {
+    seed.unwrap_or_else(|| {
+        let seed = random();
+        // Printed to stderr so it doesn't end up in the output of `generate`
+        eprintln!("Dealing seed {seed}");
+        seed
+    })
+}
+
+/// Reads a whole file, or stdin if the path is `-`
+fn read_input(path: &Path) -> io::Result<String> {
+    if path == Path::new("-") {
+        let mut input = String::new();
+        io::stdin().read_to_string(&mut input)?;
+        Ok(input)
+    } else {
+        fs::read_to_string(path)
+    }
+}
+
+fn find_preset<S>(presets: Presets<S>, name: &str) -> Result<S, Box<dyn Error>> {
+    presets
+        .iter()
+        .find(|(preset, _)| *preset == name)
+        .map(|(_, deal)| deal())
+        .ok_or_else(|| {
+            let names = presets.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+            format!(
+                "Unknown preset {name}, expected one of {}",
+                names.join(", ")
+            )
+            .into()
+        })
+}
+
+/// Reads a deal as JSON like `generate` prints it, or as text in the game's own layout
+fn read_deal<S>(path: &Path) -> Result<S, Box<dyn Error>>
+where
+    S: FromStr<Err = ParseError> + DeserializeOwned,
+{
+    let input = read_input(path)?;
+    if input.trim_start().starts_with('{') {
+        Ok(serde_json::from_str(&input)?)
+    } else {
+        Ok(input.parse()?)
+    }
+}
+
+fn fortune_deal(deal: &DealArgs) -> Result<FortunesFoundation, Box<dyn Error>> {
+    match (&deal.input, &deal.preset) {
+        (Some(path), _) => read_deal(path),
+        (None, Some(name)) => find_preset(FORTUNE_PRESETS, name),
+        (None, None) => Ok(FortunesFoundation::deal(deal.seed())),
+    }
+}
+
+fn russian_deal(deal: &DealArgs) -> Result<ProletariatsPatience, Box<dyn Error>> {
+    match (&deal.input, &deal.preset) {
+        (Some(path), _) => read_deal(path),
+        (None, Some(name)) => find_preset(RUSSIAN_PRESETS, name),
+        (None, None) => Ok(ProletariatsPatience::deal(deal.seed())),
+    }
+}
+
+fn cribbage_deal(deal: &DealArgs) -> Result<CribbageSolitaire, Box<dyn Error>> {
+    match (&deal.input, &deal.preset) {
+        (Some(path), _) => read_deal(path),
+        (None, Some(name)) => find_preset(CRIBBAGE_PRESETS, name),
+        (None, None) => Ok(CribbageSolitaire::deal(deal.seed())),
+    }
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<(), Box<dyn Error>> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+fn wait_for_key() {
+    io::stdin()
+        .read_line(&mut String::new())
+        .expect("Failed to read line");
+}
+
+fn clear_screen() {
+    print!("\x1B[2J\x1B[1;1H");
+}
+
+fn is_win<S: State>(state: &S) -> bool {
+    state
+        .end_status()
+        .is_some_and(|end_state| end_state.is_win())
+}
+
+/// Picks the next move with one of the solvers that plays one move at a time,
+/// along with the statistics of the search if the solver keeps them
+fn next_move<S>(
+    state: &S,
+    solver: Solver,
+    limits: &Limits,
+    budget: &SearchBudget,
+    observer: &mut dyn SearchObserver,
+) -> (Option<<S as State>::Action>, Option<SearchStats>)
+where
+    S: State + Clone,
+{
+    match solver {
+        Solver::Minimax => {
+            let best = best_move(state, limits.move_depth(), budget);
+            (best.map(|(mv, _)| mv), None)
+        }
+        Solver::ParallelMinimax => {
+            let best = parallel_best_move(state, limits.move_depth(), budget, limits.threads);
+            (best.map(|(mv, _)| mv), None)
+        }
+        Solver::Mcts => {
+            let config = MctsConfig {
+                iterations: Some(limits.iterations),
+                budget: budget.clone(),
+                rollout: Rollout::Greedy { epsilon: 20 },
+                ..MctsConfig::default()
+            };
+            let result = mcts_move(state, &config, observer);
+            (result.best().cloned(), Some(result.stats))
+        }
+        _ => unreachable!("{solver:?} searches for a whole line"),
+    }
+}
+
+/// What `find_line` found
+struct Line<A> {
+    outcome: SearchOutcome<A>,
+    /// The winning line if there is one, otherwise the best partial line, which may be empty
+    path: Vec<A>,
+    /// `None` for the minimax solvers, which don't keep statistics
+    stats: Option<SearchStats>,
+}
+
+/// Runs a solver to completion, reporting its progress to `observer`
+fn find_line<S>(
+    state: S,
+    solver: Solver,
+    limits: &Limits,
+    observer: &mut dyn SearchObserver,
+) -> Line<<S as State>::Action>
+where
+    S: Reversible + Clone + Debug,
+{
+    if solver.is_per_move() {
+        // Play until the game ends, the solver gives up, the line starts going in circles or the
+        // time for the whole line runs out
+        let deadline = limits
+            .time
+            .and_then(|time| Instant::now().checked_add(time));
+        let mut path = Vec::new();
+        let mut seen = SearchPath::new();
+        let mut state = state;
+        let mut stats: Option<SearchStats> = None;
+        let mut stopped = None;
+        seen.enter(&state);
+        while state.end_status().is_none() {
+            let budget = limits.line_move_budget(deadline);
+            stopped = budget.exhausted(0);
+            if stopped.is_some() {
+                break;
+            }
+            let (mv, move_stats) = next_move(&state, solver, limits, &budget, observer);
+            if let Some(move_stats) = move_stats {
+                stats.get_or_insert_default().add_search(&move_stats);
+            }
+            let Some(mv) = mv else {
+                stopped = budget.exhausted(0);
+                break;
+            };
+            state = state.act(&mv);
+            path.push(mv);
+            if !seen.enter(&state) {
+                break;
+            }
+        }
+        let outcome = if is_win(&state) {
+            SearchOutcome::Solved(path.clone())
+        } else {
+            SearchOutcome::Inconclusive {
+                reason: stopped.unwrap_or(StopReason::NotExhaustive),
+            }
+        };
+        return Line {
+            outcome,
+            path,
+            stats,
+        };
+    }
+
+    let budget = limits.budget();
+    let result = match solver {
+        Solver::Dfs => dfs_r(state, limits.depth, &budget, observer),
+        Solver::DfsInPlace => dfs_in_place(state, limits.depth, &budget, observer),
+        Solver::ParallelDfs => parallel_dfs(state, limits.depth, &budget, limits.threads, observer),
+        Solver::OptimalDfs => optimal_dfs(state, &budget, observer),
+        Solver::Astar => astar(state, &budget, observer),
+        Solver::Idastar => idastar(state, limits.memory * 1024 * 1024, &budget, observer),
+        Solver::Beam => {
+            let config = BeamConfig {
+                width: limits.width,
+                max_depth: limits.depth,
+                budget,
+                tie_break: limits.tie_break,
+            };
+            let result = beam_search(state, &config, observer);
+            return Line {
+                outcome: result.outcome,
+                path: result.path,
+                stats: Some(result.stats),
+            };
+        }
+        Solver::Minimax | Solver::ParallelMinimax | Solver::Mcts => {
+            unreachable!("Solver was checked to search for a whole line")
+        }
+    };
+    let path = result.outcome.clone().into_path().unwrap_or_default();
+    Line {
+        outcome: result.outcome,
+        path,
+        stats: Some(result.stats),
+    }
+}
+
+/// Draws a search's statistics over one line of stderr, at most 10 times a second
+#[derive(Default)]
+struct ProgressLine {
+    last_drawn: Option<Instant>,
+}
+
+impl ProgressLine {
+    /// Clears the line, so the next output starts on an empty line
+    fn clear(&self) {
+        if self.last_drawn.is_some() {
+            eprint!("\r\x1B[K");
+        }
+    }
+}
+
+impl SearchObserver for ProgressLine {
+    fn progress(&mut self, stats: &SearchStats) {
+        if self
+            .last_drawn
+            .is_some_and(|drawn| drawn.elapsed() < Duration::from_millis(100))
+        {
+            return;
+        }
+        self.last_drawn = Some(Instant::now());
+        eprint!("\r{stats}\x1B[K");
+    }
+}
+
+fn solve<S>(
+    state: S,
+    solver: Solver,
+    limits: &Limits,
+    step: bool,
+    output: Option<(&Path, Game)>,
+) -> Result<(), Box<dyn Error>>
+where
+    S: Reversible + Clone + Debug + Display + Serialize,
+    <S as State>::Action: Display + Serialize,
+{
+    println!("{state}");
+    let start = Instant::now();
+    let mut progress = ProgressLine::default();
+    let Line {
+        outcome,
+        path,
+        stats,
+    } = find_line(state.clone(), solver, limits, &mut progress);
+    let elapsed = start.elapsed();
+    progress.clear();
+    if let Some(stats) = &stats {
+        println!("{stats}");
+    }
+    match &outcome {
+        SearchOutcome::ProvenUnsolvable { states_explored } => {
+            println!(
+                "Deal is unsolvable, proven after exploring {states_explored} states in {:.2}s",
+                elapsed.as_secs_f32()
+            );
+            return Ok(());
+        }
+        SearchOutcome::Inconclusive { reason } if path.is_empty() => {
+            println!(
+                "No solution found in {:.2}s, {reason}",
+                elapsed.as_secs_f32()
+            );
+            return Ok(());
+        }
+        _ => (),
+    }
+
+    let mut final_state = state.clone();
+    for mv in &path {
+        final_state = final_state.act(mv);
+    }
+    if let Some((output, game)) = output {
+        let solution = Solution::new(
+            game,
+            solver,
+            state.clone(),
+            path.clone(),
+            elapsed,
+            stats.map(|stats| stats.nodes_expanded),
+        );
+        fs::write(output, serde_json::to_string_pretty(&solution)?)?;
+    }
+    let elapsed = elapsed.as_secs_f32();
+    match outcome {
+        SearchOutcome::Inconclusive { reason } => println!(
+            "No solution found in {elapsed:.2}s, {reason}. The best line has {} moves with score {}",
+            path.len(),
+            final_state.evaluate(false)
+        ),
+        _ => println!("Found solution with {} moves in {elapsed:.2}s", path.len()),
+    }
+
+    if step {
+        wait_for_key();
+        let mut state = state;
+        for mv in path {
+            clear_screen();
+            state = state.act(&mv);
+            println!("Score: {}", state.evaluate(true));
+            println!("\n{}\n", state.describe_action(&mv));
+            println!("{state}");
+            wait_for_key();
+        }
+    } else {
+        for mv in describe_line(&state, &path) {
+            println!("{mv}");
+        }
+        println!("\n{final_state}");
+    }
+
+    Ok(())
+}
+
+/// Describes each move of a line, as played from `state`
+fn describe_line<S>(state: &S, path: &[<S as State>::Action]) -> Vec<String>
+where
+    S: State + Clone,
+    <S as State>::Action: Display,
+{
+    let mut state = state.clone();
+    path.iter()
+        .map(|mv| {
+            state = state.act(mv);
+            state.describe_action(mv)
+        })
+        .collect()
+}
+
+/// Runs the solver on each seeded deal in turn, printing the outcomes as it goes
+fn survey<S>(
+    deal: fn(u64) -> S,
+    game: Game,
+    solver: Solver,
+    seeds: Range<u64>,
+    limits: &Limits,
+) -> Survey
+where
+    S: Reversible + Clone + Debug,
+{
+    let deals = seeds
+        .map(|seed| {
+            let state = deal(seed);
+            let start = Instant::now();
+            let mut progress = ProgressLine::default();
+            let line = find_line(state, solver, limits, &mut progress);
+            let elapsed = start.elapsed();
+            progress.clear();
+
+            let moves = line.outcome.is_solved().then_some(line.path.len());
+            let outcome = Outcome::from(&line.outcome);
+            let seconds = elapsed.as_secs_f64();
+            match moves {
+                Some(moves) => println!("Deal {seed}: solved in {moves} moves ({seconds:.2}s)"),
+                None => println!("Deal {seed}: {} ({seconds:.2}s)", outcome.name()),
+            }
+            DealReport {
+                seed,
+                outcome,
+                moves,
+                nodes: line.stats.map(|stats| stats.nodes_expanded),
+                seconds,
+            }
+        })
+        .collect();
+
+    Survey {
+        game,
+        solver,
+        deals,
+    }
+}
+
+fn play<S>(mut state: S, limits: &Limits) -> Result<(), Box<dyn Error>>
+where
+    S: State + Clone + Display,
+    <S as State>::Action: Display + PartialEq,
+{
+    loop {
+        let mut actions = state.possible_actions();
+        actions.sort_by(|a, b| {
+            let a_state = state.act(a);
+            let b_state = state.act(b);
+            a_state.evaluate(false).total_cmp(&b_state.evaluate(false))
+        });
+        actions.reverse();
+        clear_screen();
+
+        let mut best_idx = None;
+        if actions.len() > 1 {
+            let depth = limits.move_depth();
+            let budget = limits.move_budget();
+            if let Some((best, _)) = parallel_best_move(&state, depth, &budget, limits.threads) {
+                let idx = actions
+                    .iter()
+                    .position(|m| m == &best)
+                    .expect("Best move should be a possible move");
+                best_idx = Some(idx);
+                println!("Best move is {idx}: {best}");
+            } else {
+                println!("Could not determine best move");
+            }
+        } else if !actions.is_empty() {
+            best_idx = Some(0);
+        }
+        println!("Score: {}", state.evaluate(true));
+        println!("{state}");
+
+        if let Some(end_state) = state.end_status() {
+            println!(
+                "{}",
+                if end_state.is_win() {
+                    "You win!"
+                } else {
+                    "Game over!"
+                }
+            );
+            return Ok(());
+        }
+        if actions.is_empty() {
+            println!("No moves remaining, game over!");
+            return Ok(());
+        }
+
+        display_moves(&actions);
+        // Keep asking until we get a move we can make
+        loop {
+            println!("Enter move index (or q to quit):");
+            let mut input_line = String::new();
+            if io::stdin().read_line(&mut input_line)? == 0 {
+                return Ok(());
+            }
+            let input = match (input_line.trim(), best_idx) {
+                ("", Some(idx)) => idx.to_string(),
+                (input, _) => input.to_owned(),
+            };
+            if input == "q" {
+                return Ok(());
+            }
+
+            match input.parse::<usize>().ok().and_then(|idx| actions.get(idx)) {
+                Some(mv) => match state.try_act(mv) {
+                    Ok(next_state) => {
+                        state = next_state;
+                        break;
+                    }
+                    Err(err) => println!("Can't play {mv}: {err}"),
+                },
+                None => println!("{input} is not a move index"),
+            }
+        }
+    }
+}
+
+fn replay<S>(solution: serde_json::Value, step: bool) -> Result<(), Box<dyn Error>>
+where
+    S: State + Clone + Display + DeserializeOwned,
+    <S as State>::Action: Display + PartialEq + DeserializeOwned,
+{
+    let solution: Solution<S, <S as State>::Action> = serde_json::from_value(solution)?;
+    let positions = solution.positions().map_err(|(idx, err)| {
+        format!(
+            "Move {} ({}) is illegal: {err}",
+            idx + 1,
+            solution.moves[idx]
+        )
+    })?;
+    let final_state = positions.last().expect("Positions start with the deal");
+
+    println!(
+        "{} moves found by {:?} in {:.2}s",
+        solution.moves.len(),
+        solution.solver,
+        solution.seconds
+    );
+    println!("{}", solution.deal);
+    for (idx, (mv, state)) in solution.moves.iter().zip(&positions[1..]).enumerate() {
+        if step {
+            wait_for_key();
+            clear_screen();
+            println!("Score: {}", state.evaluate(true));
+        }
+        println!("\n{}: {}\n", idx + 1, state.describe_action(mv));
+        println!("{state}");
+    }
+
+    println!(
+        "\n{} after {} moves, score {}",
+        if is_win(final_state) {
+            "Won"
+        } else {
+            "Not won"
+        },
+        solution.moves.len(),
+        final_state.evaluate(false)
+    );
+    if is_win(final_state) != solution.won || final_state.evaluate(false) != solution.score {
+        println!(
+            "The solution claims it {} with score {}",
+            if solution.won { "wins" } else { "doesn't win" },
+            solution.score
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use clap::{Parser, ValueEnum};
+
+    use crate::beam::TieBreak;
+    use crate::cli::{
+        describe_line, find_line, find_preset, parse_seconds, parse_tie_break, parse_width,
+        read_deal, replay, survey, Cli, Command, Limits,
+    };
+    use crate::cribbage::CribbageSolitaire;
+    use crate::fortune::FortunesFoundation;
+    use crate::game::Game;
+    use crate::russian::ProletariatsPatience;
+    use crate::search::{SearchOutcome, StopReason};
+    use crate::solution::Solution;
+    use crate::solver::Solver;
+    use crate::states::{FORTUNE_PRESETS, RUSSIAN_PRESETS};
+    use crate::survey::Outcome;
+    use crate::testing::nearly_sorted_fortune;
+
+    #[test]
+    fn test_parse_solve() {
+        let cli = Cli::try_parse_from([
+            "solver",
+            "solve",
+            "--game",
+            "russian",
+            "--preset",
+            "russian_3",
+            "--solver",
+            "beam",
+            "--depth",
+            "20",
+            "--time",
+            "1.5",
+            "--tie-break",
+            "random:7",
+            "--output",
+            "solution.json",
+        ])
+        .unwrap();
+        let Command::Solve {
+            deal,
+            solver,
+            limits,
+            step,
+            output,
+        } = cli.command
+        else {
+            panic!("expected the solve command");
+        };
+        assert_eq!(deal.game, Game::Russian);
+        assert_eq!(deal.preset.as_deref(), Some("russian_3"));
+        assert_eq!(solver, Solver::Beam);
+        assert_eq!(limits.depth, Some(20));
+        assert_eq!(limits.time, Some(Duration::from_millis(1500)));
+        assert_eq!(limits.tie_break, TieBreak::Random { seed: 7 });
+        assert!(!step);
+        assert_eq!(output, Some(PathBuf::from("solution.json")));
+    }
+
+    #[test]
+    fn test_parse_tie_break() {
+        assert_eq!(parse_tie_break("generated"), Ok(TieBreak::Generated));
+        assert_eq!(
+            parse_tie_break("random:3"),
+            Ok(TieBreak::Random { seed: 3 })
+        );
+        assert!(parse_tie_break("random").is_err());
+        assert!(parse_tie_break("random:x").is_err());
+    }
+
+    #[test]
+    fn test_parse_seconds() {
+        assert_eq!(parse_seconds("0.25"), Ok(Duration::from_millis(250)));
+        assert!(parse_seconds("-1").is_err());
+        assert!(parse_seconds("NaN").is_err());
+        assert!(parse_seconds("soon").is_err());
+        assert!(Cli::try_parse_from(["solver", "solve", "--time", "-1"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_width() {
+        // A beam has to keep at least one state at each depth
+        assert!(parse_width("0").is_err());
+        assert!(parse_width("wide").is_err());
+        assert!(Cli::try_parse_from(["solver", "solve", "--width", "0"]).is_err());
+        let cli = Cli::try_parse_from(["solver", "solve", "--width", "1"]).unwrap();
+        let Command::Solve { limits, .. } = cli.command else {
+            panic!("expected the solve command");
+        };
+        assert_eq!(limits.width, 1);
+    }
+
+    #[test]
+    fn test_read_invalid_deal() {
+        let path = std::env::temp_dir().join(format!("invalid-deal-{}.json", std::process::id()));
+        let mut deal = serde_json::to_value(ProletariatsPatience::deal(1)).unwrap();
+        deal["tableau"][0][0] = deal["tableau"][1][0].clone();
+        fs::write(&path, deal.to_string()).unwrap();
+        let err = read_deal::<ProletariatsPatience>(&path).err().unwrap();
+        assert!(err.to_string().contains("more than once"), "{err}");
+
+        let mut deal = serde_json::to_value(FortunesFoundation::deal(1)).unwrap();
+        deal["tableau"][0] = serde_json::json!([]);
+        fs::write(&path, deal.to_string()).unwrap();
+        assert!(read_deal::<FortunesFoundation>(&path).is_err());
+
+        // A minor foundation without its ace can't be read as a deal or the start of a solution
+        let mut deal = serde_json::to_value(FortunesFoundation::deal(1)).unwrap();
+        let cards = deal["minor_foundations"][0].take();
+        deal["minor_foundations"][0] = serde_json::json!([]);
+        let cards = cards.as_array().unwrap().iter();
+        deal["tableau"][5] = cards
+            .map(|card| serde_json::json!({ "Minor": card }))
+            .collect();
+        fs::write(&path, deal.to_string()).unwrap();
+        let err = read_deal::<FortunesFoundation>(&path).err().unwrap();
+        assert!(
+            err.to_string()
+                .contains("must start exactly one foundation"),
+            "{err}"
+        );
+        let solution = Solution::new(
+            Game::Fortune,
+            Solver::Astar,
+            FortunesFoundation::deal(1),
+            Vec::new(),
+            Duration::ZERO,
+            None,
+        );
+        let mut solution = serde_json::to_value(solution).unwrap();
+        solution["deal"] = deal;
+        let err = replay::<FortunesFoundation>(solution, false).err().unwrap();
+        assert!(
+            err.to_string()
+                .contains("must start exactly one foundation"),
+            "{err}"
+        );
+
+        fs::write(
+            &path,
+            serde_json::to_string(&FortunesFoundation::deal(1)).unwrap(),
+        )
+        .unwrap();
+        assert!(read_deal::<FortunesFoundation>(&path).is_ok());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_solution_names() {
+        // Solutions name the game and solver the same way as the command line does
+        for solver in Solver::value_variants() {
+            let name = solver.to_possible_value().unwrap().get_name().to_owned();
+            assert_eq!(serde_json::to_value(solver).unwrap(), name);
+        }
+        for game in Game::value_variants() {
+            let name = game.to_possible_value().unwrap().get_name().to_owned();
+            assert_eq!(serde_json::to_value(game).unwrap(), name);
+        }
+    }
+
+    #[test]
+    fn test_input_conflicts_with_preset() {
+        let cli = Cli::try_parse_from(["solver", "solve", "--input", "-", "--preset", "fortune_1"]);
+        assert!(cli.is_err());
+    }
+
+    #[test]
+    fn test_find_preset() {
+        assert!(find_preset(FORTUNE_PRESETS, "fortune_6").is_ok());
+        let err = find_preset(RUSSIAN_PRESETS, "fortune_6").err().unwrap();
+        assert!(err.to_string().contains("russian_3"));
+    }
+
+    #[test]
+    fn test_find_line() {
+        let limits = Limits {
+            depth: Some(3),
+            time: None,
+            threads: Some(2),
+            max_nodes: None,
+            max_memory: None,
+            memory: 16,
+            width: 100,
+            tie_break: TieBreak::CostToGo,
+            iterations: 100,
+        };
+        for solver in [Solver::Minimax, Solver::Idastar, Solver::Astar] {
+            let line = find_line(nearly_sorted_fortune(), solver, &limits, &mut ());
+            assert!(line.outcome.is_solved(), "{solver:?}");
+            // Minimax doesn't count the states it expands
+            assert_eq!(
+                line.stats.is_some(),
+                solver != Solver::Minimax,
+                "{solver:?}"
+            );
+            assert_eq!(line.path.len(), 1, "{solver:?}");
+        }
+
+        let limits = Limits {
+            max_nodes: Some(0),
+            ..limits
+        };
+        for solver in [Solver::Astar, Solver::Mcts] {
+            let line = find_line(nearly_sorted_fortune(), solver, &limits, &mut ());
+            assert_eq!(
+                line.outcome,
+                SearchOutcome::Inconclusive {
+                    reason: StopReason::MaxNodes
+                },
+                "{solver:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_describe_line() {
+        let limits = Limits {
+            depth: None,
+            time: None,
+            threads: None,
+            max_nodes: None,
+            max_memory: None,
+            memory: 16,
+            width: 100,
+            tie_break: TieBreak::CostToGo,
+            iterations: 100,
+        };
+        let state = nearly_sorted_fortune();
+        let line = find_line(state.clone(), Solver::Astar, &limits, &mut ());
+        let moves = describe_line(&state, &line.path);
+        // The one move unblocks the wands, which then all build onto their foundation
+        assert_eq!(moves.len(), 1);
+        assert!(moves[0].starts_with(&line.path[0].to_string()));
+        assert!(moves[0].ends_with("(built 2W 3W 4W 5W 6W 7W 8W 9W 10W JW QW KW)"));
+    }
+
+    #[test]
+    fn test_survey() {
+        let limits = Limits {
+            depth: None,
+            time: Some(Duration::from_secs(5)),
+            threads: None,
+            max_nodes: None,
+            max_memory: None,
+            memory: 16,
+            width: 10,
+            tie_break: TieBreak::CostToGo,
+            iterations: 100,
+        };
+        let beam = survey(
+            CribbageSolitaire::deal,
+            Game::Cribbage,
+            Solver::Beam,
+            3..5,
+            &limits,
+        );
+        assert_eq!(
+            beam.deals.iter().map(|deal| deal.seed).collect::<Vec<_>>(),
+            [3, 4]
+        );
+        for deal in &beam.deals {
+            // Beam search doesn't try every position, so it can't prove a deal unsolvable
+            assert_ne!(deal.outcome, Outcome::Unsolvable);
+            assert_eq!(deal.moves.is_some(), deal.outcome == Outcome::Solved);
+        }
+
+        // The time limit covers the whole deal, however many moves a per-move solver plays
+        let limits = Limits {
+            depth: Some(30),
+            time: Some(Duration::from_millis(200)),
+            ..limits
+        };
+        let timed = survey(
+            ProletariatsPatience::deal,
+            Game::Russian,
+            Solver::Minimax,
+            0..1,
+            &limits,
+        );
+        assert_eq!(timed.deals[0].outcome, Outcome::TimedOut);
+        assert!(timed.deals[0].seconds < 1.0, "{:?}", timed.deals[0]);
+    }
+}
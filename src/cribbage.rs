@@ -1,13 +1,17 @@
-use std::fmt::Debug;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Debug, Display, Formatter};
 use std::hash::Hash;
+use std::str::FromStr;
 
-use rand::prelude::SliceRandom;
-use rand::thread_rng;
+use derivative::Derivative;
 use serde::{Deserialize, Serialize};
 use strum::{EnumIter, IntoEnumIterator};
 
-use crate::game::{Card, EndState, Rank, State, Suit};
-use crate::game::Rank::{Jack, Ten};
+use crate::game::{
+    heap_size_of, parse_grid_row, seeded_shuffle, words, Card, Deal, DealError, EndState, History,
+    MoveError, ParseError, Reversible, State,
+};
+use crate::game::Rank::Jack;
 
 // Game rules:
 // Play cards from the tableau to the stack one at a time, scoring points based on the card (see 'Scoring')
@@ -24,137 +28,108 @@ use crate::game::Rank::{Jack, Ten};
 // Set of 2, 3, or 4 of the same card: +2/+6/+12 points
 // Run of 3 to 7 cards, in any order, such as K-J-Q or 2-4-3-A: +3 to +7 points
 
-#[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+/// The most points playing one card can score: opening the stack with a jack, making 15 or 31,
+/// completing a set of 4 and a run of 7
+const MAX_CARD_POINTS: u16 = 2 + 2 + 12 + 7;
+
+/// Only the score, stack and tableau affect the rest of the game, so states reached through
+/// different orders of moves are considered equal
+///
+/// # JSON
+/// An object with these fields:
+/// - `score`: the points scored so far
+/// - `stack`: the cards on the current stack, in the order they were played
+/// - `tableau`: 4 lists of cards from the top of the column down, so the last card is playable
+/// - `history`: the moves played so far, oldest first. Optional, and empty if left out.
+///
+/// Cards are written like `game::Card`. Moves are `"NewStack"` or
+/// `{"Add": {"column": "One", "card": card}}`, with the columns named `One` to `Four`.
+///
+/// Games are checked with `Deal::validate` as they're read.
+#[derive(Derivative, Clone, Eq, Serialize, Deserialize)]
+#[derivative(Debug, Hash, PartialEq)]
+#[serde(try_from = "UncheckedCribbage")]
 pub struct CribbageSolitaire {
-    score: u8,
+    score: u16,
     stack: Vec<Card>,
     tableau: [Vec<Card>; 4],
-    history: Vec<Move>,
+    #[derivative(PartialEq = "ignore")]
+    #[derivative(Hash = "ignore")]
+    #[derivative(Debug = "ignore")]
+    #[serde(default)]
+    history: History<Move>,
 }
 
-impl CribbageSolitaire {
-    pub fn new(tableau: [Vec<Card>; 4]) -> Self {
-        Self {
-            tableau,
-            ..Self::default()
-        }
+/// A game as read from JSON, before it's been validated
+#[derive(Deserialize)]
+struct UncheckedCribbage {
+    score: u16,
+    stack: Vec<Card>,
+    tableau: [Vec<Card>; 4],
+    #[serde(default)]
+    history: History<Move>,
+}
+
+impl TryFrom<UncheckedCribbage> for CribbageSolitaire {
+    type Error = DealError;
+
+    fn try_from(unchecked: UncheckedCribbage) -> Result<Self, DealError> {
+        let state = Self {
+            score: unchecked.score,
+            stack: unchecked.stack,
+            tableau: unchecked.tableau,
+            history: unchecked.history,
+        };
+        state.validate()?;
+        Ok(state)
     }
+}
 
-    pub fn default() -> Self {
+impl CribbageSolitaire {
+    pub fn new(tableau: [Vec<Card>; 4]) -> Self {
         Self {
             score: 0,
             stack: Vec::new(),
-            tableau: Self::random_tableau(),
-            history: Vec::new(),
+            tableau,
+            history: History::new(),
         }
     }
 
-    pub fn from_str(input: &str) -> Self {
-        // TODO: support parsing other properties besides tableau
-        let input = input.trim();
-        let mut tableau = [vec![], vec![], vec![], vec![]];
-        let mut rank = None;
-        let mut suit = None;
-        let mut col_idx = 0;
-        for (idx, char) in input.char_indices() {
-            // TODO: we need to handle gaps properly, not just skip whitespace
-            if char.is_whitespace() {
-                continue;
-            }
-
-            if char == '0' && input.chars().nth(idx - 1) == Some('1') {
-                rank = Some(Ten);
-            } else if let Ok(new_rank) = Rank::try_from(char.to_string()) {
-                rank = Some(new_rank);
-            }
-
-            if let Ok(new_suit) = Suit::try_from(char.to_string()) {
-                suit = Some(new_suit);
-            }
-
-            if rank.is_some() && suit.is_some() {
-                tableau[col_idx].push(Card {
-                    rank: rank.unwrap(),
-                    suit: suit.unwrap(),
-                });
-                rank = None;
-                suit = None;
-                col_idx += 1;
-            }
-        }
-
-        Self {
-            tableau,
-            ..Self::default()
-        }
+    /// Deals the cards in the order given by `seed`, so a seed always gives the same deal
+    pub fn deal(seed: u64) -> Self {
+        Self::new(Self::shuffled_tableau(seed))
     }
 
     pub fn apply_move(&self, mv: Move) -> Self {
-        let mut history = self.history.clone();
-        history.push(mv);
-
-        match mv {
-            Move::NewStack => Self {
-                score: self.score,
-                stack: Vec::new(),
-                tableau: self.tableau.clone(),
-                history,
-            },
-            Move::Add { column, card } => {
-                // Verify that this card came from the top of the given column
-                let top_card = self
-                    .peek_column(column)
-                    .expect("Given column does not have any cards");
-                if *top_card != card {
-                    panic!("Given card does not match top card from given column")
-                }
-
-                let mut score = self.score;
-                // If the first card played to the stack is a Jack: +2 points
-                if self.stack.is_empty() && card.rank == Jack {
-                    score += 2;
-                }
-                // If the stack total is exactly 15 or 31: +2 points
-                let new_stack_total = self.stack_total() + card.value();
-                if new_stack_total == 15 || new_stack_total == 31 {
-                    score += 2;
-                }
-                // If there is a set of 2, 3, or 4 of the same card: +2/+6/+12 points
-                let card_matches = self
-                    .stack
-                    .iter()
-                    .rev()
-                    .take_while(|c| c.rank == card.rank)
-                    .count();
-                score += match card_matches {
-                    0 => 0,
-                    1 => 2,
-                    2 => 6,
-                    3 => 12,
-                    _ => unreachable!("There can' be more than 4 of the same card"),
-                };
-                // TODO: check for runs
-
-                let mut stack = self.stack.clone();
-                stack.push(card);
-
-                let mut tableau = self.tableau.clone();
-                tableau[column as usize].pop();
+        let mut state = self.clone();
+        state.apply_in_place(&mv);
+        state
+    }
 
-                Self {
-                    score,
-                    stack,
-                    tableau,
-                    history,
-                }
+    /// Returns the length of the longest run formed by the top cards of the stack,
+    /// or 0 if the top 3 cards don't form a run.
+    fn run_length(stack: &[Card]) -> usize {
+        // Runs are at most 7 cards long, so we only need to check that many cards
+        let max_len = stack.len().min(7);
+        for len in (3..=max_len).rev() {
+            let mut ranks = stack[stack.len() - len..]
+                .iter()
+                .map(|card| card.rank as usize)
+                .collect::<Vec<_>>();
+            ranks.sort_unstable();
+            // A run is a set of distinct, consecutive ranks in any order
+            if ranks.windows(2).all(|pair| pair[1] == pair[0] + 1) {
+                return len;
             }
         }
+
+        0
     }
 
-    fn random_tableau() -> [Vec<Card>; 4] {
+    fn shuffled_tableau(seed: u64) -> [Vec<Card>; 4] {
         let mut cards = Card::default_deck();
 
-        cards.shuffle(&mut thread_rng());
+        seeded_shuffle(&mut cards, seed);
 
         let chunk_size = cards.len() / 4;
         [
@@ -187,10 +162,47 @@ impl CribbageSolitaire {
         moves
     }
 
+    /// Checks that a move follows the rules, so that applying it can trust it
+    pub fn check_move(&self, mv: &Move) -> Result<(), MoveError> {
+        if self.is_terminal() {
+            return Err(MoveError::GameOver);
+        }
+
+        match *mv {
+            Move::NewStack => {
+                if self.legal_moves().contains(&Move::NewStack) {
+                    Ok(())
+                } else {
+                    Err(MoveError::StackNotFinished)
+                }
+            }
+            Move::Add { column, card } => {
+                let top_card = self.peek_column(column).ok_or(MoveError::EmptyColumn {
+                    column: column as usize,
+                })?;
+                if *top_card != card {
+                    return Err(MoveError::NotOnTop {
+                        cards: card.to_string().trim().to_owned(),
+                        column: column as usize,
+                    });
+                }
+                let total = self.stack_total() + card.value();
+                if total > 31 {
+                    return Err(MoveError::StackOverflow {
+                        card: card.to_string().trim().to_owned(),
+                        total,
+                    });
+                }
+                Ok(())
+            }
+        }
+    }
+
     pub fn is_terminal(&self) -> bool {
         self.remaining_cards() == 0
     }
 
+    #[allow(dead_code)]
     pub fn display_state(&self) {
         println!("Stack:\n{:?}", self.stack);
         println!("Tableau:");
@@ -199,26 +211,31 @@ impl CribbageSolitaire {
         }
     }
 
+    #[allow(dead_code)]
     pub fn display_history(&self) {
         println!("History:");
-        for mv in &self.history {
+        for mv in self.history.to_vec() {
             println!("{mv:?}");
         }
     }
 
-    pub fn score(&self) -> u8 {
+    #[allow(dead_code)]
+    pub fn score(&self) -> u16 {
         self.score
     }
 
+    #[allow(dead_code)]
     pub fn stack(&self) -> &Vec<Card> {
         &self.stack
     }
 
+    #[allow(dead_code)]
     pub fn tableau(&self) -> &[Vec<Card>; 4] {
         &self.tableau
     }
 
-    pub fn history(&self) -> &Vec<Move> {
+    #[allow(dead_code)]
+    pub fn history(&self) -> &History<Move> {
         &self.history
     }
 
@@ -233,6 +250,20 @@ impl CribbageSolitaire {
     pub fn stack_total(&self) -> u8 {
         self.stack.iter().map(|card| card.value()).sum()
     }
+
+    /// Each card played so far scored at most `MAX_CARD_POINTS`. Keeping the score within that
+    /// also keeps it from overflowing however the rest of the cards score.
+    fn check_score(&self) -> Result<(), DealError> {
+        let played = 52 - self.remaining_cards();
+        let max = MAX_CARD_POINTS as usize * played;
+        if self.score as usize > max {
+            return Err(DealError::ScoreTooHigh {
+                score: self.score as usize,
+                max,
+            });
+        }
+        Ok(())
+    }
 }
 
 #[derive(EnumIter, Copy, Clone, Debug, Serialize, Deserialize, Eq, Hash, PartialEq)]
@@ -249,6 +280,26 @@ pub enum Move {
     Add { column: Column, card: Card },
 }
 
+impl Deal for CribbageSolitaire {
+    /// Cards that were played on earlier stacks are gone, so any subset of the deck is a deal, as
+    /// long as the stack is playable and the score could have come from the cards that are gone
+    fn validate(&self) -> Result<(), DealError> {
+        let mut seen = HashSet::new();
+        for card in self.stack.iter().chain(self.tableau.iter().flatten()) {
+            if !seen.insert(card) {
+                let card = card.to_string().trim().to_owned();
+                return Err(DealError::Duplicate { card });
+            }
+        }
+        // Summed wider than `stack_total`, which can only count stacks that follow the rules
+        let total = self.stack.iter().map(|card| card.value() as usize).sum();
+        if total > 31 {
+            return Err(DealError::StackTotal { total });
+        }
+        self.check_score()
+    }
+}
+
 impl State for CribbageSolitaire {
     type Action = Move;
 
@@ -272,15 +323,294 @@ impl State for CribbageSolitaire {
         self.apply_move(*action)
     }
 
+    fn try_act(&self, action: &Move) -> Result<Self, MoveError> {
+        self.check_move(action)?;
+        Ok(self.act(action))
+    }
+
+    fn heap_size(&self) -> usize {
+        heap_size_of(&self.tableau) + heap_size_of(std::slice::from_ref(&self.stack))
+    }
+
     fn evaluate(&self, _: bool) -> f32 {
         self.score as f32
     }
 }
 
+/// Parts of the state that a move overwrites, kept so that the move can be undone
+pub struct Undo {
+    score: u16,
+    /// The stack before it was cleared by `Move::NewStack`
+    stack: Vec<Card>,
+}
+
+impl Reversible for CribbageSolitaire {
+    type Undo = Undo;
+
+    fn apply_in_place(&mut self, mv: &Move) -> Undo {
+        let mut undo = Undo {
+            score: self.score,
+            stack: Vec::new(),
+        };
+
+        match *mv {
+            Move::NewStack => undo.stack = std::mem::take(&mut self.stack),
+            // Moves are checked by `check_move` (or generated legal), so they're trusted here
+            Move::Add { column, card } => {
+                // If the first card played to the stack is a Jack: +2 points
+                if self.stack.is_empty() && card.rank == Jack {
+                    self.score += 2;
+                }
+                // If the stack total is exactly 15 or 31: +2 points
+                let new_stack_total = self.stack_total() + card.value();
+                if new_stack_total == 15 || new_stack_total == 31 {
+                    self.score += 2;
+                }
+                // If there is a set of 2, 3, or 4 of the same card: +2/+6/+12 points
+                let card_matches = self
+                    .stack
+                    .iter()
+                    .rev()
+                    .take_while(|c| c.rank == card.rank)
+                    .count();
+                self.score += match card_matches {
+                    0 => 0,
+                    1 => 2,
+                    2 => 6,
+                    3 => 12,
+                    _ => unreachable!("There can' be more than 4 of the same card"),
+                };
+
+                self.stack.push(card);
+
+                // If there is a run of 3 to 7 cards, in any order: +3 to +7 points
+                self.score += Self::run_length(&self.stack) as u16;
+
+                self.tableau[column as usize].pop();
+            }
+        }
+        self.history = self.history.with(*mv);
+
+        undo
+    }
+
+    fn undo(&mut self, undo: Undo) {
+        match self.history.last() {
+            Some(Move::NewStack) => self.stack = undo.stack,
+            Some(&Move::Add { column, .. }) => {
+                let card = self.stack.pop().expect("Added card should be on the stack");
+                self.tableau[column as usize].push(card);
+            }
+            None => panic!("Tried to undo a move but there is no history"),
+        }
+        self.score = undo.score;
+        self.history.pop();
+    }
+}
+
+impl Display for CribbageSolitaire {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Score: {}", self.score)?;
+        write!(f, "Stack ({}):", self.stack_total())?;
+        for card in &self.stack {
+            write!(f, " {}", card.to_string().trim())?;
+        }
+        writeln!(f, "\n")?;
+
+        // Columns are printed top to bottom, so the cards that can be played are on the last line
+        let max_col_len = self.tableau.iter().map(|col| col.len()).max().unwrap_or(0);
+        for row in 0..max_col_len {
+            let line = self
+                .tableau
+                .iter()
+                .map(|col| match col.get(row) {
+                    Some(card) => card.to_string(),
+                    None => "   ".to_owned(),
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+            writeln!(f, "{}", line.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for CribbageSolitaire {
+    type Err = ParseError;
+
+    /// Reads the layout printed by `Display`:
+    /// ```text
+    /// Score: 4
+    /// Stack (15): 5♥ K♤
+    ///
+    ///  7♥  7♦  K♧ 10♧
+    ///  8♤  3♥      6♦
+    /// ```
+    /// The score and stack lines are optional. The rest is the tableau, one row per line with
+    /// 4 characters per column, read top to bottom so the cards that can be played come last.
+    /// Columns can be shorter than the others but can't have gaps. The score can't be more than
+    /// the cards that are gone could have scored.
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut state = Self::new(Default::default());
+        let mut seen = HashMap::new();
+        let mut add_card = |line_no: usize, column: usize, card: Card| {
+            if let Some((first_line, first_column)) = seen.insert(card, (line_no, column)) {
+                let card = card.to_string().trim().to_owned();
+                return Err(ParseError::at(
+                    line_no,
+                    column,
+                    format!("{card} was already dealt at line {first_line}, column {first_column}"),
+                ));
+            }
+            Ok(())
+        };
+        let mut score_line = None;
+        let mut score_at = None;
+        let mut stack_line = None;
+        let mut row = 0;
+
+        for (line_idx, line) in input.lines().enumerate() {
+            let line_no = line_idx + 1;
+            let mut words = words(line);
+            let Some((first_column, first_word)) = words.next() else {
+                continue;
+            };
+            let header = if first_word.eq_ignore_ascii_case("score:") {
+                &mut score_line
+            } else if first_word.eq_ignore_ascii_case("stack")
+                || first_word.eq_ignore_ascii_case("stack:")
+            {
+                &mut stack_line
+            } else {
+                for (column, card) in parse_grid_row(&mut state.tableau, row, line_no, line)? {
+                    add_card(line_no, column, card)?;
+                }
+                row += 1;
+                continue;
+            };
+
+            let name = first_word.trim_end_matches(':').to_lowercase();
+            if row > 0 {
+                return Err(ParseError::at(
+                    line_no,
+                    first_column,
+                    format!("the {name} has to come before the tableau"),
+                ));
+            }
+            if let Some(previous) = header.replace(line_no) {
+                return Err(ParseError::at(
+                    line_no,
+                    first_column,
+                    format!("the {name} was already given on line {previous}"),
+                ));
+            }
+
+            if name == "score" {
+                let (column, word) = words.next().ok_or_else(|| {
+                    ParseError::at(line_no, line.chars().count() + 1, "expected the score")
+                })?;
+                state.score = word.parse().map_err(|_| {
+                    ParseError::at(line_no, column, format!("expected the score, found {word}"))
+                })?;
+                score_at = Some((line_no, column));
+                if let Some((column, word)) = words.next() {
+                    return Err(ParseError::at(
+                        line_no,
+                        column,
+                        format!("expected nothing after the score, found {word}"),
+                    ));
+                }
+                continue;
+            }
+
+            // The total is optional, but has to match the cards if it's given
+            let mut words = words.peekable();
+            let total = match words.peek() {
+                Some(&(column, word)) if first_word.eq_ignore_ascii_case("stack") => {
+                    words.next();
+                    let total = word
+                        .strip_prefix('(')
+                        .and_then(|word| word.strip_suffix("):"))
+                        .and_then(|total| total.parse::<u8>().ok())
+                        .ok_or_else(|| {
+                            ParseError::at(
+                                line_no,
+                                column,
+                                format!("expected the stack total like (15):, found {word}"),
+                            )
+                        })?;
+                    Some((column, total))
+                }
+                _ => None,
+            };
+            for (column, word) in words {
+                let card = word
+                    .parse::<Card>()
+                    .map_err(|message| ParseError::at(line_no, column, message))?;
+                add_card(line_no, column, card)?;
+                state.stack.push(card);
+                if state.stack_total() > 31 {
+                    return Err(ParseError::at(
+                        line_no,
+                        column,
+                        format!(
+                            "the stack total can't go over 31, but is {}",
+                            state.stack_total()
+                        ),
+                    ));
+                }
+            }
+            if let Some((column, total)) = total {
+                if total != state.stack_total() {
+                    return Err(ParseError::at(
+                        line_no,
+                        column,
+                        format!("the stack adds up to {}, not {total}", state.stack_total()),
+                    ));
+                }
+            }
+        }
+
+        // The score can only be checked once we know how many cards have been played
+        if let (Err(error), Some((line_no, column))) = (state.check_score(), score_at) {
+            return Err(ParseError::at(line_no, column, error.to_string()));
+        }
+        Ok(state)
+    }
+}
+
+impl Display for Move {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Move::NewStack => write!(f, "New stack"),
+            // We add 1 to convert from 0-based index to 1-based index
+            Move::Add { column, card } => write!(f, "{}->Stack\t{card}", *column as usize + 1),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use crate::cribbage::CribbageSolitaire;
-    use crate::states::state_0001;
+    use std::collections::HashSet;
+
+    use crate::cribbage::{Column, CribbageSolitaire, Move};
+    use crate::game::Rank::*;
+    use crate::game::Suit::*;
+    use crate::game::{Card, Deal, DealError, MoveError, Rank, Reversible, State};
+    use crate::states::{state_0001, CRIBBAGE_PRESETS};
+
+    fn stack_of(ranks: &[Rank]) -> Vec<Card> {
+        // Alternate suits so that no two cards are identical
+        let suits = [Heart, Club, Diamond, Spade];
+        ranks
+            .iter()
+            .enumerate()
+            .map(|(i, &rank)| Card {
+                suit: suits[i % 4],
+                rank,
+            })
+            .collect()
+    }
 
     #[test]
     fn test_parser() {
@@ -296,9 +626,411 @@ JH  KH  3C  QH
 JC  9C  4S  JD
 9H  7C  4D  6H
 2C  KS  2D  5C
-10H AC  3S  QS
+10H AD  3S  QS
 2S  AH  8H  2H";
-        let state = CribbageSolitaire::from_str(input);
+        let state = input.parse::<CribbageSolitaire>().unwrap();
         assert_eq!(state, state_0001());
     }
+
+    #[test]
+    fn test_parse_round_trip() {
+        // Playing some cards leaves a score, a stack and columns of different heights
+        let mut state = state_0001();
+        let mut states = vec![state.clone(), CribbageSolitaire::deal(1)];
+        for _ in 0..12 {
+            let mv = state.legal_moves()[0];
+            state = state.apply_move(mv);
+            states.push(state.clone());
+        }
+        assert!(states
+            .iter()
+            .any(|state| state.score > 0 && !state.stack.is_empty()));
+
+        for state in states {
+            let text = state.to_string();
+            let parsed = text.parse::<CribbageSolitaire>().unwrap();
+            assert_eq!(parsed, state, "{text}");
+            assert_eq!(parsed.to_string(), text);
+        }
+    }
+
+    #[test]
+    fn test_parse_uneven_columns() {
+        let input = "Score: 8\nStack: 5H JC\n\n7H  7D  KC  10C\n8S      10D\n        QD";
+        let state = input.parse::<CribbageSolitaire>().unwrap();
+        assert_eq!(state.score, 8);
+        assert_eq!(state.stack_total(), 15);
+        let heights = state.tableau.iter().map(Vec::len).collect::<Vec<_>>();
+        assert_eq!(heights, vec![2, 1, 3, 1]);
+        assert_eq!(state.tableau[2].last().map(|card| card.rank), Some(Queen));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let parse = |text: &str| {
+            text.parse::<CribbageSolitaire>()
+                .err()
+                .map(|error| error.to_string())
+        };
+        assert_eq!(
+            parse("7H  7D\n7D").as_deref(),
+            Some("line 2, column 1: 7\u{2666} was already dealt at line 1, column 5")
+        );
+        assert_eq!(
+            parse("Stack: 5H\n5H").as_deref(),
+            Some("line 2, column 1: 5\u{2665} was already dealt at line 1, column 8")
+        );
+        assert_eq!(
+            parse("Stack (12): 5H 6C").as_deref(),
+            Some("line 1, column 7: the stack adds up to 11, not 12")
+        );
+        assert_eq!(
+            parse("Stack: KH KC QS JD").as_deref(),
+            Some("line 1, column 17: the stack total can't go over 31, but is 40")
+        );
+        assert_eq!(
+            parse("7H  7D\nScore: 2").as_deref(),
+            Some("line 2, column 1: the score has to come before the tableau")
+        );
+        assert_eq!(
+            parse("Score: 2\nScore: 3").as_deref(),
+            Some("line 2, column 1: the score was already given on line 1")
+        );
+        assert_eq!(
+            parse("Score: lots").as_deref(),
+            Some("line 1, column 8: expected the score, found lots")
+        );
+        assert_eq!(
+            parse("Score: 70000").as_deref(),
+            Some("line 1, column 8: expected the score, found 70000")
+        );
+        assert_eq!(
+            parse("Score: 2000\n7H  7D").as_deref(),
+            Some("line 1, column 8: the played cards can score at most 1150, not 2000")
+        );
+        assert_eq!(
+            parse("7H\n    7D").as_deref(),
+            Some("line 2, column 5: column 2 has a gap above 7D")
+        );
+        assert_eq!(
+            parse("7H  7D  7C  7S  8H").as_deref(),
+            Some("line 1, column 17: there are only 4 columns")
+        );
+    }
+
+    #[test]
+    fn test_run_of_three() {
+        assert_eq!(
+            CribbageSolitaire::run_length(&stack_of(&[King, Jack, Queen])),
+            3
+        );
+        assert_eq!(
+            CribbageSolitaire::run_length(&stack_of(&[Two, Ace, Three])),
+            3
+        );
+    }
+
+    #[test]
+    fn test_run_of_four() {
+        assert_eq!(
+            CribbageSolitaire::run_length(&stack_of(&[Two, Four, Three, Ace])),
+            4
+        );
+    }
+
+    #[test]
+    fn test_run_of_five() {
+        assert_eq!(
+            CribbageSolitaire::run_length(&stack_of(&[Seven, Five, Nine, Six, Eight])),
+            5
+        );
+    }
+
+    #[test]
+    fn test_run_of_six() {
+        assert_eq!(
+            CribbageSolitaire::run_length(&stack_of(&[Ace, Three, Five, Two, Six, Four])),
+            6
+        );
+    }
+
+    #[test]
+    fn test_run_of_seven() {
+        assert_eq!(
+            CribbageSolitaire::run_length(&stack_of(&[Four, Ace, Seven, Three, Six, Two, Five])),
+            7
+        );
+    }
+
+    #[test]
+    fn test_longest_run_counts() {
+        // The top 3 and top 4 cards are both runs, only the longer one scores
+        assert_eq!(
+            CribbageSolitaire::run_length(&stack_of(&[King, Four, Two, Three, Ace])),
+            4
+        );
+    }
+
+    #[test]
+    fn test_no_run() {
+        assert_eq!(CribbageSolitaire::run_length(&stack_of(&[])), 0);
+        assert_eq!(CribbageSolitaire::run_length(&stack_of(&[Two, Three])), 0);
+        assert_eq!(
+            CribbageSolitaire::run_length(&stack_of(&[Two, Four, Six])),
+            0
+        );
+        // Runs must be formed by the cards on top of the stack
+        assert_eq!(
+            CribbageSolitaire::run_length(&stack_of(&[Ace, Two, Three, Nine])),
+            0
+        );
+    }
+
+    #[test]
+    fn test_run_broken_by_duplicate() {
+        assert_eq!(
+            CribbageSolitaire::run_length(&stack_of(&[Three, Four, Four, Five])),
+            0
+        );
+        // The duplicate is buried, so only the top 3 cards form a run
+        assert_eq!(
+            CribbageSolitaire::run_length(&stack_of(&[Four, Three, Four, Five])),
+            3
+        );
+    }
+
+    #[test]
+    fn test_run_scoring() {
+        let mut state =
+            CribbageSolitaire::new([stack_of(&[Queen, Jack, King]), vec![], vec![], vec![]]);
+        for _ in 0..3 {
+            let card = *state.peek_column(Column::One).unwrap();
+            state = state.apply_move(Move::Add {
+                column: Column::One,
+                card,
+            });
+        }
+
+        // K-J-Q totals 30 and starts with a King, so the run is the only score
+        assert_eq!(state.score(), 3);
+    }
+
+    #[test]
+    fn test_try_act() {
+        let state = state_0001();
+        for mv in state.legal_moves() {
+            assert_eq!(state.try_act(&mv), Ok(state.act(&mv)));
+        }
+
+        assert_eq!(
+            state.try_act(&Move::NewStack),
+            Err(MoveError::StackNotFinished)
+        );
+        let top_card = *state
+            .peek_column(Column::One)
+            .expect("Deal should have cards in every column");
+        let other_card = *state
+            .peek_column(Column::Two)
+            .expect("Deal should have cards in every column");
+        assert_eq!(
+            state.try_act(&Move::Add {
+                column: Column::One,
+                card: other_card,
+            }),
+            Err(MoveError::NotOnTop {
+                cards: other_card.to_string().trim().to_owned(),
+                column: 0,
+            })
+        );
+        assert!(state
+            .try_act(&Move::Add {
+                column: Column::One,
+                card: top_card,
+            })
+            .is_ok());
+    }
+
+    #[test]
+    fn test_undo() {
+        let mut state = state_0001();
+        // Play until the stack is full, so that the last move starts a new stack
+        while state.legal_moves() != vec![Move::NewStack] {
+            let mv = state.legal_moves()[0];
+            state = state.apply_move(mv);
+        }
+
+        for _ in 0..3 {
+            let original = state.clone();
+            let mv = state.legal_moves()[0];
+            let undo = state.apply_in_place(&mv);
+            assert_eq!(state, original.apply_move(mv));
+            state.undo(undo);
+            assert_eq!(state, original);
+
+            state = state.apply_move(mv);
+        }
+    }
+
+    /// Counts the states a search with a visited-state cache would explore, like `dfs_r`
+    fn explored_states(state: CribbageSolitaire, cache: &mut HashSet<CribbageSolitaire>) -> usize {
+        if !cache.insert(state.clone()) {
+            return 0;
+        }
+        if state.is_terminal() {
+            return 1;
+        }
+
+        1 + state
+            .legal_moves()
+            .into_iter()
+            .map(|mv| explored_states(state.apply_move(mv), cache))
+            .sum::<usize>()
+    }
+
+    #[test]
+    fn test_transposition() {
+        let state = CribbageSolitaire::new([
+            stack_of(&[King]),
+            stack_of(&[Queen]),
+            stack_of(&[Nine]),
+            stack_of(&[Five]),
+        ]);
+        let play = |state: &CribbageSolitaire, column: Column| {
+            let card = *state.peek_column(column).unwrap();
+            state.apply_move(Move::Add { column, card })
+        };
+
+        // Both lines total 29 without scoring, so the 5 has to start a new stack
+        let a = play(
+            &play(&play(&state, Column::One), Column::Two),
+            Column::Three,
+        )
+        .apply_move(Move::NewStack);
+        let b = play(
+            &play(&play(&state, Column::Two), Column::One),
+            Column::Three,
+        )
+        .apply_move(Move::NewStack);
+        assert_ne!(a.history(), b.history());
+        assert_eq!(a, b);
+
+        let mut cache = HashSet::new();
+        cache.insert(a);
+        assert!(!cache.insert(b));
+    }
+
+    #[test]
+    fn test_explored_state_count() {
+        // Only 3 cards fit in a stack, so new stacks are started often
+        let state = CribbageSolitaire::new([
+            stack_of(&[King, Queen]),
+            stack_of(&[Queen, Jack]),
+            stack_of(&[Jack, Ten]),
+            stack_of(&[Ten, King]),
+        ]);
+
+        let explored = explored_states(state.clone(), &mut HashSet::new());
+        // Without transposition detection, every line of play is its own state
+        let lines = count_tree_nodes(&state);
+        assert!(
+            explored < lines,
+            "explored {explored} states for {lines} lines"
+        );
+    }
+
+    /// Counts every state in the game tree, including repeats
+    fn count_tree_nodes(state: &CribbageSolitaire) -> usize {
+        if state.is_terminal() {
+            return 1;
+        }
+
+        1 + state
+            .legal_moves()
+            .into_iter()
+            .map(|mv| count_tree_nodes(&state.apply_move(mv)))
+            .sum::<usize>()
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        for (name, deal) in CRIBBAGE_PRESETS {
+            let mut state = deal();
+            for _ in 0..3 {
+                let mv = state.legal_moves()[0];
+                state = state.apply_move(mv);
+            }
+            for state in [deal(), state] {
+                let json = serde_json::to_string(&state).unwrap();
+                let parsed = serde_json::from_str::<CribbageSolitaire>(&json).unwrap();
+                assert_eq!(parsed, state, "{name}");
+                assert_eq!(parsed.history.to_vec(), state.history.to_vec(), "{name}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_json_schema() {
+        let mv = Move::Add {
+            column: Column::Two,
+            card: Card {
+                suit: Spade,
+                rank: Jack,
+            },
+        };
+        assert_eq!(
+            serde_json::to_string(&[mv, Move::NewStack]).unwrap(),
+            r#"[{"Add":{"column":"Two","card":{"suit":"Spade","rank":"Jack"}}},"NewStack"]"#
+        );
+
+        let mut json = serde_json::to_value(state_0001()).unwrap();
+        json.as_object_mut().unwrap().remove("history");
+        let state = serde_json::from_value::<CribbageSolitaire>(json).unwrap();
+        assert_eq!(state, state_0001());
+    }
+
+    #[test]
+    fn test_deal() {
+        let state = CribbageSolitaire::deal(42);
+        assert_eq!(state, CribbageSolitaire::deal(42));
+        assert_ne!(state, CribbageSolitaire::deal(43));
+        assert!(state.tableau.iter().all(|column| column.len() == 13));
+        let cards = state.tableau.iter().flatten().collect::<HashSet<_>>();
+        assert_eq!(cards.len(), 52);
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut state = CribbageSolitaire::deal(42);
+        assert_eq!(state.validate(), Ok(()));
+        state.score = 255;
+        assert_eq!(
+            state.validate(),
+            Err(DealError::ScoreTooHigh { score: 255, max: 0 })
+        );
+
+        // Every card that's gone could have scored
+        state.tableau[0].pop();
+        state.score = 23;
+        assert_eq!(state.validate(), Ok(()));
+        state.score = 24;
+        assert_eq!(
+            state.validate(),
+            Err(DealError::ScoreTooHigh { score: 24, max: 23 })
+        );
+
+        let state = CribbageSolitaire {
+            score: 255,
+            stack: stack_of(&[King, Queen, Jack, Ten, Nine, Eight, Four]),
+            ..CribbageSolitaire::new(Default::default())
+        };
+        assert_eq!(state.validate(), Err(DealError::StackTotal { total: 61 }));
+
+        // Games are validated as they're read
+        let json = serde_json::to_value(&state).unwrap();
+        let err = serde_json::from_value::<CribbageSolitaire>(json).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            DealError::StackTotal { total: 61 }.to_string()
+        );
+    }
 }
@@ -1,70 +1,108 @@
 use std::collections::HashSet;
 use std::fmt::Debug;
-use std::io;
-use std::time::{Duration, Instant};
 
-use crate::game::{EndState, State};
-use crate::PEAK_ALLOC;
+use crate::game::{EndState, Reversible, State};
+use crate::search::{
+    Meter, SearchBudget, SearchObserver, SearchOutcome, SearchResult, StopReason, Tracker,
+};
 
+/// What stays the same throughout one depth-first search
+struct Search<'t, 'a, S> {
+    /// Every state searched so far
+    cache: HashSet<S>,
+    tracker: &'t mut Tracker<'a>,
+    max_depth: usize,
+}
+
+impl<'t, 'a, S: State + Clone> Search<'t, 'a, S> {
+    fn new(tracker: &'t mut Tracker<'a>, max_depth: Option<usize>) -> Self {
+        Self {
+            cache: HashSet::new(), // TODO: replace with lru cache
+            tracker,
+            max_depth: max_depth.unwrap_or(usize::MAX),
+        }
+    }
+
+    /// Checks whether the search can go on to a state at `depth`, and records it if it hasn't
+    /// been searched before. Games that have ended are let through at the depth limit, so a win
+    /// that takes exactly `max_depth` moves is found, like in `parallel_dfs`.
+    /// Returns `Err(None)` for states that were already searched.
+    fn enter(&mut self, state: &S, depth: usize) -> Result<(), Option<StopReason>> {
+        if depth >= self.max_depth && state.end_status().is_none() {
+            return Err(Some(StopReason::MaxDepth));
+        }
+        if let Some(reason) = self.tracker.out_of_budget() {
+            return Err(Some(reason));
+        }
+        // If the insert returns `false`, we've already seen this state so we can skip
+        if !self.cache.insert(state.clone()) {
+            self.tracker.prune_duplicate();
+            return Err(None);
+        }
+        Ok(())
+    }
+
+    /// Turns the result of the search into an outcome
+    fn outcome(
+        &self,
+        result: Result<Vec<<S as State>::Action>, Option<StopReason>>,
+    ) -> SearchOutcome<<S as State>::Action> {
+        match result {
+            Ok(path) => SearchOutcome::Solved(path),
+            Err(None) => SearchOutcome::ProvenUnsolvable {
+                states_explored: self.cache.len(),
+            },
+            Err(Some(reason)) => SearchOutcome::Inconclusive { reason },
+        }
+    }
+}
+
+/// Depth-first search that skips states it has already visited.
+/// A deal is only proven unsolvable if no state was cut off by `max_depth` or the budget.
 pub fn dfs_r<S: State + Clone + Debug>(
     state: S,
     max_depth: Option<usize>,
-    max_search_time: Option<Duration>,
-) -> Option<Vec<<S as State>::Action>> {
+    budget: &SearchBudget,
+    observer: &mut dyn SearchObserver,
+) -> SearchResult<<S as State>::Action> {
+    let mut tracker = Tracker::new(budget, observer);
+    let outcome = dfs_r_tracked(state, max_depth, &mut tracker);
+    tracker.finish(outcome)
+}
+
+fn dfs_r_tracked<S: State + Clone + Debug>(
+    state: S,
+    max_depth: Option<usize>,
+    tracker: &mut Tracker,
+) -> SearchOutcome<<S as State>::Action> {
+    /// Returns the winning line, or why the search of this subtree stopped short (if it did)
     fn backtrack<S: State + Clone + Debug>(
         state: S,
         path: Vec<<S as State>::Action>,
-        cache: &mut HashSet<S>,
         depth: usize,
-        search_start_time: Instant,
-        max_depth: usize,
-        max_search_time: Duration,
-    ) -> Option<Vec<<S as State>::Action>> {
-        if depth >= max_depth || search_start_time.elapsed() > max_search_time {
-            return None;
-        }
-        // If the insert returns `false`, we've already seen this state so we can skip
-        if !cache.insert(state.clone()) {
-            return None;
-        }
-        if depth == max_depth {
-            // println!("\nChecking state {state:?} with path {path:?}\n");
-            println!("\n Checking state {state:?}\n");
-            for action in state.possible_actions() {
-                println!("{action:?}");
-            }
-            io::stdin()
-                .read_line(&mut String::new())
-                .expect("Failed to read line");
-        }
+        search: &mut Search<S>,
+    ) -> Result<Vec<<S as State>::Action>, Option<StopReason>> {
+        search.enter(&state, depth)?;
 
         if let Some(end_state) = state.end_status() {
             return match end_state {
-                EndState::Win => Some(path),
-                EndState::Loss => None,
+                EndState::Win => Ok(path),
+                EndState::Loss => Err(None),
             };
         }
 
+        search.tracker.expand(depth);
+        let mut stopped = None;
         let mut path = path.clone();
-        for mv in state.possible_actions() {
+        for mv in state.actions_after(path.last()) {
             let next_state = state.act(&mv);
+            search.tracker.generate();
             path.push(mv.clone());
 
-            print!(
-                "\rDepth: {depth}\tRAM: {:.2}MB",
-                PEAK_ALLOC.current_usage_as_mb()
-            );
-            let result = backtrack(
-                next_state,
-                path.clone(),
-                cache,
-                depth + 1,
-                search_start_time,
-                max_depth,
-                max_search_time,
-            );
-            if result.is_some() {
-                return result;
+            match backtrack(next_state, path.clone(), depth + 1, search) {
+                Ok(path) => return Ok(path),
+                Err(Some(reason)) if reason.ends_search() => return Err(Some(reason)),
+                Err(reason) => stopped = stopped.or(reason),
             }
 
             path.pop();
@@ -72,41 +110,203 @@ pub fn dfs_r<S: State + Clone + Debug>(
             // state.revert(&mv);
         }
 
-        None
+        Err(stopped)
     }
 
-    let mut cache = HashSet::new(); // TODO: replace with lru cache
-    backtrack(
-        state,
-        vec![],
-        &mut cache,
-        0,
-        Instant::now(),
-        max_depth.unwrap_or(usize::MAX),
-        max_search_time.unwrap_or(Duration::MAX),
-    )
+    let mut search = Search::new(tracker, max_depth);
+    let result = backtrack(state, vec![], 0, &mut search);
+    search.outcome(result)
 }
 
-pub fn optimal_dfs<S: State + Clone + Debug>(state: S) -> Option<Vec<<S as State>::Action>> {
-    let max_search_time = Duration::from_secs(60);
-    let mut best_depth = usize::MAX;
-    // let mut best_depth = 1000;
+/// Same search as `dfs_r`, but walks the tree by applying and undoing moves on a single state
+/// rather than creating a new state for every move
+pub fn dfs_in_place<S: Reversible + Clone + Debug>(
+    mut state: S,
+    max_depth: Option<usize>,
+    budget: &SearchBudget,
+    observer: &mut dyn SearchObserver,
+) -> SearchResult<<S as State>::Action> {
+    /// Returns `Ok` if `path` now holds the winning line, like `dfs_r`'s `backtrack`
+    fn backtrack<S: Reversible + Clone + Debug>(
+        state: &mut S,
+        path: &mut Vec<<S as State>::Action>,
+        depth: usize,
+        search: &mut Search<S>,
+    ) -> Result<(), Option<StopReason>> {
+        search.enter(state, depth)?;
+
+        if let Some(end_state) = state.end_status() {
+            return if end_state.is_win() {
+                Ok(())
+            } else {
+                Err(None)
+            };
+        }
+
+        search.tracker.expand(depth);
+        let mut stopped = None;
+        for mv in state.actions_after(path.last()) {
+            let undo = state.apply_in_place(&mv);
+            search.tracker.generate();
+            path.push(mv);
+
+            match backtrack(state, path, depth + 1, search) {
+                Ok(()) => return Ok(()),
+                Err(Some(reason)) if reason.ends_search() => return Err(Some(reason)),
+                Err(reason) => stopped = stopped.or(reason),
+            }
+
+            path.pop();
+            state.undo(undo);
+        }
+
+        Err(stopped)
+    }
+
+    let mut tracker = Tracker::new(budget, observer);
+    let mut search = Search::new(&mut tracker, max_depth);
+    let mut path = vec![];
+    let result = backtrack(&mut state, &mut path, 0, &mut search);
+    let outcome = search.outcome(result.map(|()| path));
+    tracker.finish(outcome)
+}
+
+/// Repeats `dfs_r` with the depth limit lowered below the last solution, until no shorter one is
+/// found. If the first search doesn't find a solution, its outcome is returned as it is.
+/// The budget covers all of the searches, so the shortest solution found so far is returned when
+/// it runs out. The statistics cover all of the searches too.
+pub fn optimal_dfs<S: State + Clone + Debug>(
+    state: S,
+    budget: &SearchBudget,
+    observer: &mut dyn SearchObserver,
+) -> SearchResult<<S as State>::Action> {
+    let mut tracker = Tracker::new(budget, observer);
+    let mut max_depth = None;
     let mut best_path = None;
     loop {
-        let start = Instant::now();
-        if let Some(path) = dfs_r(state.clone(), Some(best_depth), Some(max_search_time)) {
-            println!(
-                "\nFound solution with {} moves in {:.2}s\n",
-                path.len(),
-                start.elapsed().as_secs_f32()
-            );
-            best_depth = path.len();
-            best_path = Some(path);
-        } else {
-            println!("\nNo solution found for max depth {best_depth}");
-            break;
+        match dfs_r_tracked(state.clone(), max_depth, &mut tracker) {
+            SearchOutcome::Solved(path) if path.is_empty() => {
+                return tracker.finish(SearchOutcome::Solved(path));
+            }
+            SearchOutcome::Solved(path) => {
+                // Only a shorter solution would be an improvement
+                max_depth = Some(path.len() - 1);
+                best_path = Some(path);
+            }
+            outcome => {
+                let outcome = match best_path {
+                    Some(path) => SearchOutcome::Solved(path),
+                    None => outcome,
+                };
+                return tracker.finish(outcome);
+            }
         }
     }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+
+    use crate::dfs::{dfs_in_place, dfs_r, optimal_dfs};
+    use crate::search::{SearchBudget, SearchOutcome, StopReason};
+    use crate::testing::{nearly_solved_russian, DeadEnd, NumberLine};
 
-    best_path
+    #[test]
+    fn test_unsolvable() {
+        assert_eq!(
+            dfs_r(DeadEnd(0), None, &SearchBudget::default(), &mut ()).outcome,
+            SearchOutcome::ProvenUnsolvable { states_explored: 7 }
+        );
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        // The shortest win takes 4 moves, so a failed search can't prove anything
+        assert_eq!(
+            dfs_r(NumberLine(0), Some(3), &SearchBudget::default(), &mut ()).outcome,
+            SearchOutcome::Inconclusive {
+                reason: StopReason::MaxDepth
+            }
+        );
+        // Even when the deal can't be won within the limit
+        assert_eq!(
+            dfs_r(DeadEnd(0), Some(2), &SearchBudget::default(), &mut ()).outcome,
+            SearchOutcome::Inconclusive {
+                reason: StopReason::MaxDepth
+            }
+        );
+    }
+
+    #[test]
+    fn test_win_at_depth_limit() {
+        // A win that takes exactly as many moves as the limit allows is found
+        let budget = SearchBudget::default();
+        let path = dfs_r(nearly_solved_russian(), Some(2), &budget, &mut ())
+            .outcome
+            .into_path()
+            .expect("The shortest win fits within the limit");
+        assert_eq!(path.len(), 2);
+        let path = dfs_in_place(nearly_solved_russian(), Some(2), &budget, &mut ())
+            .outcome
+            .into_path()
+            .expect("The shortest win fits within the limit");
+        assert_eq!(path.len(), 2);
+    }
+
+    #[test]
+    fn test_budget() {
+        let budget = SearchBudget {
+            max_nodes: Some(2),
+            ..SearchBudget::default()
+        };
+        let result = dfs_r(DeadEnd(0), None, &budget, &mut ());
+        assert_eq!(
+            result.outcome,
+            SearchOutcome::Inconclusive {
+                reason: StopReason::MaxNodes
+            }
+        );
+        assert_eq!(result.stats.nodes_expanded, 2);
+
+        let budget = SearchBudget {
+            cancel: Some(Arc::new(AtomicBool::new(true))),
+            ..SearchBudget::default()
+        };
+        let result = dfs_in_place(nearly_solved_russian(), None, &budget, &mut ());
+        assert_eq!(
+            result.outcome,
+            SearchOutcome::Inconclusive {
+                reason: StopReason::Cancelled
+            }
+        );
+        assert_eq!(result.stats.nodes_expanded, 0);
+    }
+
+    #[test]
+    fn test_optimal_dfs_budget() {
+        let shortest = optimal_dfs(nearly_solved_russian(), &SearchBudget::default(), &mut ())
+            .outcome
+            .into_path()
+            .expect("Deal should be solvable");
+        assert_eq!(shortest.len(), 2);
+
+        // Running out of budget after the first solution still returns it
+        let first = dfs_r(
+            nearly_solved_russian(),
+            None,
+            &SearchBudget::default(),
+            &mut (),
+        );
+        let budget = SearchBudget {
+            max_nodes: Some(first.stats.nodes_expanded + 1),
+            ..SearchBudget::default()
+        };
+        let path = optimal_dfs(nearly_solved_russian(), &budget, &mut ())
+            .outcome
+            .into_path()
+            .expect("The first solution is found within the budget");
+        assert!(path.len() >= shortest.len());
+    }
 }
@@ -1,14 +1,22 @@
 use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::fmt::{Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
 
 use derivative::Derivative;
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
 
 use crate::fortune::Suit::{Cups, Pentacles, Swords, Wands};
 use crate::fortune::TarotCard::{Major, Minor};
-use crate::game::{EndState, Rank, State};
+use crate::game::{
+    canonical_order, heap_size_of, seeded_shuffle, words, Deal, DealError, EndState, History,
+    MoveError, ParseError, Rank, Reversible, State,
+};
 use crate::game::Rank::Ace;
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct MajorArcana {
     pub rank: u8, // TODO: is this fine as just u8? Might be nice as a better type
 }
@@ -19,7 +27,7 @@ impl MajorArcana {
     }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum Suit {
     Wands,
     Swords,
@@ -43,19 +51,20 @@ impl Display for Suit {
     }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Card {
     pub rank: Rank,
     pub suit: Suit,
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum TarotCard {
     Minor(Card),
     Major(MajorArcana),
 }
 
 impl TarotCard {
+    #[allow(dead_code)]
     pub fn is_major(&self) -> bool {
         matches!(self, Major(..))
     }
@@ -101,27 +110,103 @@ impl Display for MaybeTarotCard {
 ///  - The minor arcana are built up by suit from A to K.
 /// - A card may be placed above the minor arcana foundation,
 ///   but will block further minor arcana from moving there.
-#[derive(Derivative, Clone, Eq)]
-#[derivative(Debug, Hash, PartialEq)]
+///
+/// Where a column sits in the tableau doesn't affect the game, so `Hash` and `Eq` treat states as
+/// equal if their columns are the same up to reordering.
+/// The tableau itself is never reordered, so moves always refer to the real column indices.
+///
+/// # JSON
+/// An object with these fields, where columns are indices from 0 and list their cards from the
+/// bottom up:
+/// - `minor_foundations`: 4 lists of minor cards, each starting with its ace
+/// - `major_foundations`: 2 lists of major arcana, built up from 0 and down from 21
+/// - `tableau`: 11 lists of cards
+/// - `free_cell`: a card, or `null`
+/// - `history`: the turns played so far, oldest first. Optional, and empty if left out.
+///
+/// Minor cards are written `{"rank": "Ten", "suit": "Cups"}` with the ranks and suits named
+/// like `game::Rank` and `Suit`, and major arcana `{"rank": 17}`. Where either kind can appear
+/// they're wrapped as `{"Minor": card}` or `{"Major": card}`.
+/// Turns are `{"mv": move, "builds": [move, ...]}`, and moves are one of
+/// `{"Stack": {"card", "from", "to"}}`, `{"Free": {"card", "from"}}`,
+/// `{"Unfree": {"card", "to"}}` or `{"Build": {"card", "from"}}`.
+///
+/// Games are checked with `Deal::validate` as they're read, since building and displaying them
+/// rely on every minor foundation holding its ace.
+#[derive(Derivative, Clone, Serialize, Deserialize)]
+#[derivative(Debug)]
+#[serde(try_from = "UncheckedFortune")]
 pub struct FortunesFoundation {
     minor_foundations: [Vec<Card>; 4],
     major_foundations: [Vec<MajorArcana>; 2],
     tableau: [Vec<TarotCard>; 11],
     free_cell: Option<TarotCard>,
-    #[derivative(PartialEq = "ignore")]
-    #[derivative(Hash = "ignore")]
     #[derivative(Debug = "ignore")]
-    history: Vec<Move>,
+    #[serde(default)]
+    history: History<Turn>,
+}
+
+/// A game as read from JSON, before it's been validated
+#[derive(Deserialize)]
+struct UncheckedFortune {
+    minor_foundations: [Vec<Card>; 4],
+    major_foundations: [Vec<MajorArcana>; 2],
+    tableau: [Vec<TarotCard>; 11],
+    free_cell: Option<TarotCard>,
+    #[serde(default)]
+    history: History<Turn>,
+}
+
+impl TryFrom<UncheckedFortune> for FortunesFoundation {
+    type Error = DealError;
+
+    fn try_from(unchecked: UncheckedFortune) -> Result<Self, DealError> {
+        let state = Self {
+            minor_foundations: unchecked.minor_foundations,
+            major_foundations: unchecked.major_foundations,
+            tableau: unchecked.tableau,
+            free_cell: unchecked.free_cell,
+            history: unchecked.history,
+        };
+        state.validate()?;
+        Ok(state)
+    }
 }
-// TODO: try a custom impl of Hash that makes a compressed representation to take advantage of symmetry
-// e.g. empty columns always on right since it doesn't matter _where_ the column is
 
 impl FortunesFoundation {
+    /// Creates a game from a deal, building any cards that can go straight to the foundations
     pub fn new(tableau: [Vec<TarotCard>; 11]) -> Self {
-        Self {
+        let mut state = Self {
             tableau,
             ..Self::default()
+        };
+        state.auto_build();
+        state
+    }
+
+    /// Deals the cards in the order given by `seed`, so a seed always gives the same deal.
+    /// Like the real game, the aces start on the foundations and the other 70 cards are dealt into
+    /// 10 columns of 7, leaving the middle column empty.
+    pub fn deal(seed: u64) -> Self {
+        let majors = (0..=21).map(|rank| Major(MajorArcana { rank }));
+        let minors = [Wands, Swords, Cups, Pentacles]
+            .into_iter()
+            .flat_map(|suit| {
+                Rank::iter()
+                    .filter(|&rank| rank != Ace)
+                    .map(move |rank| Minor(Card { rank, suit }))
+            });
+        let mut cards = majors.chain(minors).collect::<Vec<_>>();
+        seeded_shuffle(&mut cards, seed);
+
+        let mut tableau: [Vec<TarotCard>; 11] = Default::default();
+        let middle = tableau.len() / 2;
+        for (idx, column) in tableau.iter_mut().enumerate() {
+            if idx != middle {
+                column.extend(cards.drain(..7));
+            }
         }
+        Self::new(tableau)
     }
 
     fn default() -> Self {
@@ -159,8 +244,205 @@ impl FortunesFoundation {
                 vec![],
             ],
             free_cell: None,
-            history: vec![],
+            history: History::new(),
+        }
+    }
+
+    /// Moves the top card of the given column to a foundation, if it can be built there
+    fn try_build(&mut self, from: usize) -> Option<Move> {
+        let card = *self.tableau[from].last()?;
+        match card {
+            Major(inner) => {
+                // Add to the left major foundation if this is the first card or
+                // its rank is 1 rank higher than the current top of the foundation
+                let foundation = if inner.rank == 0
+                    || self.major_foundations[0]
+                        .last()
+                        .is_some_and(|fc| inner.rank == fc.rank + 1)
+                {
+                    0
+                } else if inner.rank == 21
+                    || self.major_foundations[1]
+                        .last()
+                        .is_some_and(|fc| inner.rank + 1 == fc.rank)
+                {
+                    // Similarly for the right, except rank 21 or 1 rank lower than current
+                    1
+                } else {
+                    return None;
+                };
+                self.major_foundations[foundation].push(inner);
+            }
+
+            Minor(inner) => {
+                // We can only move minor cards to the foundation if it is unblocked
+                // (free cell blocks foundations)
+                if self.free_cell.is_some() {
+                    return None;
+                }
+                let foundation = self.minor_foundations.iter_mut().find(|foundation| {
+                    let foundation_card = foundation
+                        .last()
+                        .expect("Minor foundation should be non-empty");
+                    inner.suit == foundation_card.suit
+                        && inner.rank as usize == foundation_card.rank as usize + 1
+                })?;
+                foundation.push(inner);
+            }
+        }
+        self.tableau[from].pop();
+
+        Some(Move::Build { card, from })
+    }
+
+    /// Builds every card that can move to a foundation, like the real game does after each move.
+    /// We need to restart the check every time one completes, since building a card can uncover
+    /// another card that can be built.
+    fn auto_build(&mut self) -> Vec<Move> {
+        let mut builds = Vec::new();
+        let mut built_any = true;
+        while built_any {
+            built_any = false;
+            for from in 0..self.tableau.len() {
+                while let Some(build) = self.try_build(from) {
+                    builds.push(build);
+                    built_any = true;
+                }
+            }
+        }
+
+        builds
+    }
+
+    /// Moves a built card from the top of its foundation back to the given column
+    fn unbuild(&mut self, card: TarotCard, from: usize) {
+        // The card is on top of whichever foundation it was built on
+        match card {
+            Major(inner) => {
+                let foundation = self
+                    .major_foundations
+                    .iter_mut()
+                    .find(|foundation| foundation.last() == Some(&inner))
+                    .expect("Built card should be on top of a major foundation");
+                foundation.pop();
+            }
+            Minor(inner) => {
+                let foundation = self
+                    .minor_foundations
+                    .iter_mut()
+                    .find(|foundation| foundation.last() == Some(&inner))
+                    .expect("Built card should be on top of a minor foundation");
+                foundation.pop();
+            }
+        }
+        self.tableau[from].push(card);
+    }
+
+    /// Checks that a move follows the rules, so that applying it can trust it
+    pub fn check_move(&self, mv: &Move) -> Result<(), MoveError> {
+        if self.end_status().is_some() {
+            return Err(MoveError::GameOver);
+        }
+        let check_column = |column: usize| {
+            if column < self.tableau.len() {
+                Ok(())
+            } else {
+                Err(MoveError::NoSuchColumn { column })
+            }
+        };
+        let check_top_card = |card: &TarotCard, column: usize| {
+            check_column(column)?;
+            match self.tableau[column].last() {
+                None => Err(MoveError::EmptyColumn { column }),
+                Some(top_card) if top_card != card => Err(MoveError::NotOnTop {
+                    cards: card.to_string().trim().to_owned(),
+                    column,
+                }),
+                Some(_) => Ok(()),
+            }
+        };
+        // Cards can go on an empty column, or on a card they can stack on
+        let check_destination = |card: &TarotCard, column: usize| {
+            check_column(column)?;
+            match self.tableau[column].last() {
+                Some(top_card) if !card.can_stack_on(top_card) => Err(MoveError::CannotStack {
+                    cards: card.to_string().trim().to_owned(),
+                    column,
+                }),
+                _ => Ok(()),
+            }
+        };
+
+        match mv {
+            Move::Free { card, from } => {
+                check_top_card(card, *from)?;
+                if self.free_cell.is_some() {
+                    return Err(MoveError::FreeCellOccupied);
+                }
+            }
+            Move::Unfree { card, to } => {
+                match self.free_cell {
+                    None => return Err(MoveError::FreeCellEmpty),
+                    Some(free_card) if free_card != *card => {
+                        return Err(MoveError::NotInFreeCell {
+                            card: card.to_string().trim().to_owned(),
+                        })
+                    }
+                    Some(_) => (),
+                }
+                check_destination(card, *to)?;
+            }
+            Move::Stack { card, from, to } => {
+                check_top_card(card, *from)?;
+                check_column(*to)?;
+                if from == to {
+                    return Err(MoveError::SameColumn { column: *from });
+                }
+                check_destination(card, *to)?;
+            }
+            Move::Build { .. } => return Err(MoveError::ManualBuild),
+        }
+
+        Ok(())
+    }
+
+    #[allow(dead_code)]
+    pub fn history(&self) -> &History<Turn> {
+        &self.history
+    }
+
+    #[allow(dead_code)]
+    pub fn display_history(&self) {
+        for turn in self.history.to_vec() {
+            println!("{turn}");
+        }
+    }
+
+    /// Writes the tableau, free cell and major arcana foundations in the format read by `from_str`
+    pub fn to_layout(&self) -> String {
+        let mut layout = String::new();
+        for column in &self.tableau {
+            let cards = column
+                .iter()
+                .map(|card| card.to_string().trim().to_owned())
+                .collect::<Vec<_>>();
+            if cards.is_empty() {
+                layout.push('-');
+            } else {
+                layout.push_str(&cards.join(" "));
+            }
+            layout.push('\n');
+        }
+        if let Some(card) = self.free_cell {
+            layout.push_str(&format!("free {}\n", card.to_string().trim()));
         }
+        let [up, down] = self.major_foundations.each_ref().map(|foundation| {
+            foundation
+                .last()
+                .map_or("-".to_owned(), |card| card.rank.to_string())
+        });
+        layout.push_str(&format!("majors {up} {down}\n"));
+        layout
     }
 
     pub fn is_terminal(&self) -> bool {
@@ -181,7 +463,7 @@ impl FortunesFoundation {
     }
 }
 
-#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
 pub enum Move {
     Stack {
         card: TarotCard,
@@ -202,13 +484,134 @@ pub enum Move {
     },
 }
 
+/// A move made by the player, along with the cards that were built automatically after it
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+pub struct Turn {
+    pub mv: Move,
+    /// `Move::Build`s in the order they happened
+    pub builds: Vec<Move>,
+}
+
 impl Move {
-    fn is_free(&self) -> bool {
-        matches!(self, Move::Free { .. })
+    /// Whether this move puts the card moved by `previous` straight back where it came from
+    pub fn undoes(&self, previous: &Move) -> bool {
+        match (*previous, *self) {
+            (Move::Free { card, from }, Move::Unfree { card: back, to })
+            | (
+                Move::Unfree { card, to: from },
+                Move::Free {
+                    card: back,
+                    from: to,
+                },
+            ) => card == back && from == to,
+            (
+                Move::Stack { card, from, to },
+                Move::Stack {
+                    card: back,
+                    from: back_from,
+                    to: back_to,
+                },
+            ) => card == back && from == back_to && to == back_from,
+            _ => false,
+        }
+    }
+}
+
+impl PartialEq for FortunesFoundation {
+    fn eq(&self, other: &Self) -> bool {
+        self.free_cell == other.free_cell
+            && self.minor_foundations == other.minor_foundations
+            && self.major_foundations == other.major_foundations
+            && canonical_order(&self.tableau)
+                .iter()
+                .zip(canonical_order(&other.tableau))
+                .all(|(&a, b)| self.tableau[a] == other.tableau[b])
     }
+}
 
-    fn is_unfree(&self) -> bool {
-        matches!(self, Move::Unfree { .. })
+impl Eq for FortunesFoundation {}
+
+impl Hash for FortunesFoundation {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.minor_foundations.hash(state);
+        self.major_foundations.hash(state);
+        self.free_cell.hash(state);
+        for idx in canonical_order(&self.tableau) {
+            self.tableau[idx].hash(state);
+        }
+    }
+}
+
+impl Deal for FortunesFoundation {
+    /// Every card is in the tableau, the free cell or on a foundation, and the foundations are
+    /// built in order: each suit of the minor arcana up from its ace, and the major arcana up from
+    /// 0 and down from 21
+    fn validate(&self) -> Result<(), DealError> {
+        let name = |card: &TarotCard| card.to_string().trim().to_owned();
+        // Building and displaying the game rely on every minor foundation holding at least its ace
+        for suit in [Wands, Swords, Cups, Pentacles] {
+            let started = self
+                .minor_foundations
+                .iter()
+                .filter(|foundation| foundation.first().is_some_and(|card| card.suit == suit))
+                .count();
+            if started != 1 {
+                return Err(DealError::MissingFoundation {
+                    card: name(&Minor(Card { rank: Ace, suit })),
+                });
+            }
+        }
+        for foundation in &self.minor_foundations {
+            let suit = foundation.first().map(|card| card.suit);
+            for (idx, card) in foundation.iter().enumerate() {
+                if Some(card.suit) != suit || card.rank as usize != idx + 1 {
+                    return Err(DealError::OutOfOrder {
+                        card: name(&Minor(*card)),
+                    });
+                }
+            }
+        }
+        let [up, down] = &self.major_foundations;
+        let up = up.iter().enumerate();
+        let down = down.iter().enumerate().map(|(idx, card)| (21 - idx, card));
+        for (rank, card) in up.chain(down) {
+            if card.rank as usize != rank {
+                return Err(DealError::OutOfOrder {
+                    card: name(&Major(*card)),
+                });
+            }
+        }
+
+        let mut seen = HashSet::new();
+        let built = self
+            .minor_foundations
+            .iter()
+            .flatten()
+            .map(|&card| Minor(card));
+        let built = built.chain(
+            self.major_foundations
+                .iter()
+                .flatten()
+                .map(|&card| Major(card)),
+        );
+        let cards = built
+            .chain(self.free_cell)
+            .chain(self.tableau.iter().flatten().copied());
+        for card in cards {
+            if matches!(card, Major(MajorArcana { rank }) if rank > 21) {
+                return Err(DealError::ExcludedRank { card: name(&card) });
+            }
+            if !seen.insert(card) {
+                return Err(DealError::Duplicate { card: name(&card) });
+            }
+        }
+        if seen.len() != 74 {
+            return Err(DealError::WrongCardCount {
+                expected: 74,
+                found: seen.len(),
+            });
+        }
+        Ok(())
     }
 }
 
@@ -230,15 +633,20 @@ impl State for FortunesFoundation {
     /// Legal moves:
     /// - All top cards can be freed if the free cell is empty
     /// - A top card can move from one column to another if it is stackable on the destination
-    /// - A top card can move to the foundation if it is +1 rank of the foundation of its suit
-    ///  - Major arcana is a bit of a special case since it counts up _and_ down in 2 foundations
     /// - A free card can be unfreed if it can stack on any top card
+    ///
+    /// Cards are never moved to the foundations by hand, since `act` builds them automatically
     fn possible_actions(&self) -> Vec<Self::Action> {
         let mut moves = Vec::new();
 
+        // Columns are visited in canonical order so that permutations of the same tableau generate
+        // the same moves in the same order (up to the column indices)
+        let column_order = canonical_order(&self.tableau);
+
         // Flag for only adding one instance of unfreeing to an empty col, since they're identical
         let mut added_empty_column_unfree = false;
-        for (from_idx, from_col) in self.tableau.iter().enumerate() {
+        for &from_idx in &column_order {
+            let from_col = &self.tableau[from_idx];
             if from_col.is_empty() {
                 if !added_empty_column_unfree {
                     if let Some(free_card) = self.free_cell {
@@ -267,62 +675,11 @@ impl State for FortunesFoundation {
                 }),
             }
 
-            // Add moves related to building foundations
-            // Since these moves are automatically performed in the real game, if we come across
-            // one here, we'll return _only_ that move to force it
-            // TODO: these shouldn't be moves, we should just replicate the auto-build
-            match top_card {
-                Major(inner) => {
-                    // TODO: these conditions could be merged since the body is duplicated
-                    // Add to the left major foundation if this is the first card or
-                    // its rank is 1 rank higher than the current top of the foundation
-                    if inner.rank == 0
-                        || self.major_foundations[0]
-                            .last()
-                            .is_some_and(|fc| inner.rank as usize == fc.rank as usize + 1)
-                    {
-                        return vec![Move::Build {
-                            card: *top_card,
-                            from: from_idx,
-                        }];
-                    } else if inner.rank == 21
-                        || self.major_foundations[1]
-                            .last()
-                            .is_some_and(|fc| inner.rank as usize == fc.rank as usize - 1)
-                    {
-                        // Similarly for the right, except rank 21 or 1 rank lower than current
-                        return vec![Move::Build {
-                            card: *top_card,
-                            from: from_idx,
-                        }];
-                    }
-                }
-
-                Minor(inner) => {
-                    // We can only move minor cards to the foundation if it is unblocked
-                    // (free cell blocks foundations)
-                    if self.free_cell.is_none() {
-                        for foundation in self.minor_foundations.iter() {
-                            let foundation_card = foundation
-                                .last()
-                                .expect("Minor foundation should be non-empty");
-                            if inner.suit == foundation_card.suit
-                                && inner.rank as usize == foundation_card.rank as usize + 1
-                            {
-                                return vec![Move::Build {
-                                    card: *top_card,
-                                    from: from_idx,
-                                }];
-                            }
-                        }
-                    }
-                }
-            }
-
             // Add moves related to moving cards between columns
             // Flag for only adding 1 move to an empty column, since they're all identical
             let mut added_empty_column_move = false;
-            for (to_idx, to_col) in self.tableau.iter().enumerate() {
+            for &to_idx in &column_order {
+                let to_col = &self.tableau[to_idx];
                 // Skip current column
                 if from_idx == to_idx {
                     continue;
@@ -360,50 +717,6 @@ impl State for FortunesFoundation {
             }
         }
 
-        // Remove all moves we used in the last `n` turns to prevent cycles
-        // TODO: There's probably a better way to handle cycles than this
-        moves.retain(|m| {
-            !self
-                .history
-                .iter()
-                .rev()
-                .take(11)
-                .collect::<Vec<_>>()
-                .contains(&m)
-        });
-
-        if !self.history.is_empty() {
-            let last_move = self
-                .history
-                .last()
-                .expect("History was checked to be non-empty");
-
-            // Remove moves that are just moving the same cards as the last move
-            let last_card = match last_move {
-                Move::Free { card, .. }
-                | Move::Unfree { card, .. }
-                | Move::Stack { card, .. }
-                | Move::Build { card, .. } => card,
-            };
-            moves.retain(|m| {
-                let card = match m {
-                    Move::Free { card, .. }
-                    | Move::Unfree { card, .. }
-                    | Move::Stack { card, .. }
-                    | Move::Build { card, .. } => card,
-                };
-                card != last_card
-            });
-
-            // If we just freed a card, we can't unfree it immediately
-            if last_move.is_free() {
-                moves.retain(|m| !m.is_unfree());
-            }
-        }
-
-        // TODO: similarly we can remove moves that are reversions of the previous move
-        // TODO: Should we remove _all_ moves that could just be undone next turn?
-
         // Sort descending so the "best" moves are at the front
         moves.sort_unstable();
         moves.reverse();
@@ -412,106 +725,43 @@ impl State for FortunesFoundation {
     }
 
     fn act(&self, action: &Self::Action) -> Self {
-        let mut history = self.history.clone();
-        history.push(action.clone());
-
-        let mut tableau = self.tableau.clone();
-        let mut new_state = match *action {
-            Move::Free { card, from } => {
-                tableau[from]
-                    .pop()
-                    .expect("Tableau column to be non-empty when freeing card");
-                Self {
-                    free_cell: Some(card),
-                    tableau,
-                    minor_foundations: self.minor_foundations.clone(),
-                    major_foundations: self.major_foundations.clone(),
-                    history,
-                }
-            }
-
-            Move::Unfree { card, to } => {
-                tableau[to].push(card);
-                Self {
-                    free_cell: None,
-                    tableau,
-                    minor_foundations: self.minor_foundations.clone(),
-                    major_foundations: self.major_foundations.clone(),
-                    history,
-                }
-            }
-
-            Move::Build { card, from } => {
-                tableau[from].pop().unwrap();
-                let mut major_foundations = self.major_foundations.clone();
-                let mut minor_foundations = self.minor_foundations.clone();
-                match card {
-                    Major(card) => {
-                        // Decide which foundation to stack on (left/right)
-                        if card.rank == 0
-                            || major_foundations[0]
-                                .last()
-                                .is_some_and(|fc| card.can_stack_on(fc))
-                        {
-                            major_foundations[0].push(card);
-                        } else if card.rank == 21
-                            || major_foundations[1]
-                                .last()
-                                .is_some_and(|fc| card.can_stack_on(fc))
-                        {
-                            major_foundations[1].push(card);
-                        }
-                    }
+        let mut new_state = self.clone();
+        new_state.apply_in_place(action);
+        new_state
+    }
 
-                    Minor(inner) => {
-                        // Find the foundation that this card can stack on
-                        for foundation in minor_foundations.iter_mut() {
-                            let top_card = foundation
-                                .last()
-                                .expect("Minor foundation should be non-empty");
-                            if inner.suit == top_card.suit
-                                && inner.rank as usize == top_card.rank as usize + 1
-                            {
-                                foundation.push(inner);
-                            }
-                        }
-                    }
-                }
+    fn try_act(&self, action: &Move) -> Result<Self, MoveError> {
+        self.check_move(action)?;
+        Ok(self.act(action))
+    }
 
-                Self {
-                    major_foundations,
-                    minor_foundations,
-                    tableau,
-                    free_cell: self.free_cell,
-                    history,
-                }
-            }
+    /// Undoing the previous move leads straight back to the state before it, unless cards were
+    /// built in between. Parking a card to let the one under it build and then putting it back
+    /// is a real move, so it's only skipped when `previous` built nothing.
+    fn is_redundant(&self, previous: &Move, action: &Move) -> bool {
+        action.undoes(previous)
+            && self
+                .history
+                .last()
+                .is_some_and(|turn| turn.mv == *previous && turn.builds.is_empty())
+    }
 
-            Move::Stack { card, from, to } => {
-                tableau[from]
-                    .pop()
-                    .expect("Tableau column to be non-empty when moving card");
-                tableau[to].push(card);
-                Self {
-                    tableau,
-                    minor_foundations: self.minor_foundations.clone(),
-                    major_foundations: self.major_foundations.clone(),
-                    free_cell: self.free_cell,
-                    history,
-                }
-            }
-        };
+    fn last_action(&self) -> Option<&Move> {
+        self.history.last().map(|turn| &turn.mv)
+    }
 
-        // TODO: Check if any cards can be moved to the foundation
-        // We'll need to restart the check every time one completes, since moving a card could
-        // make another move valid
-        // for column in new_state.tableau.iter_mut() {
-        //     for card in column {
-        //
-        //     }
-        // }
+    /// Adds the cards that were built onto the foundations after the move
+    fn describe_action(&self, action: &Move) -> String {
+        match self.history.last() {
+            Some(turn) if turn.mv == *action => turn.to_string(),
+            _ => action.to_string(),
+        }
+    }
 
-        new_state
+    fn heap_size(&self) -> usize {
+        heap_size_of(&self.minor_foundations)
+            + heap_size_of(&self.major_foundations)
+            + heap_size_of(&self.tableau)
     }
 
     fn evaluate(&self, print_components: bool) -> f32 {
@@ -579,10 +829,98 @@ impl State for FortunesFoundation {
         score += self.possible_actions().len() as f32 * 0.25;
 
         // Subtract score for each remaining card
-        score -= self.tableau.iter().map(|c| c.iter().count()).sum::<usize>() as f32;
+        score -= self.tableau.iter().map(|c| c.len()).sum::<usize>() as f32;
 
         score
     }
+
+    /// Building is automatic, so only cards that can never be built where they are need a move.
+    /// That's any card in the free cell, and any minor card with a lower card of the same suit
+    /// somewhere below it in its column.
+    /// A move only moves a single card, so it can fix at most one of these.
+    fn cost_to_go(&self) -> usize {
+        let mut cost = self.free_cell.iter().count();
+        for column in &self.tableau {
+            for (depth, card) in column.iter().enumerate() {
+                if let Minor(card) = card {
+                    if column[..depth].iter().any(|below| {
+                        matches!(below, Minor(below) if below.suit == card.suit && below.rank < card.rank)
+                    }) {
+                        cost += 1;
+                    }
+                }
+            }
+        }
+
+        cost
+    }
+}
+
+impl Reversible for FortunesFoundation {
+    /// Every move can be undone by moving the same card back
+    type Undo = Move;
+
+    fn apply_in_place(&mut self, action: &Move) -> Move {
+        // Moves are checked by `check_move` (or generated legal), so they're trusted here
+        match *action {
+            Move::Free { card, from } => {
+                self.tableau[from].pop();
+                self.free_cell = Some(card);
+            }
+
+            Move::Unfree { card, to } => {
+                self.tableau[to].push(card);
+                self.free_cell = None;
+            }
+
+            Move::Build { .. } => unreachable!("Cards are only built automatically"),
+
+            Move::Stack { card, from, to } => {
+                self.tableau[from].pop();
+                self.tableau[to].push(card);
+            }
+        };
+
+        let builds = self.auto_build();
+        self.history = self.history.with(Turn {
+            mv: *action,
+            builds,
+        });
+
+        *action
+    }
+
+    fn undo(&mut self, action: Move) {
+        let turn = self
+            .history
+            .last()
+            .expect("Tried to undo a move but there is no history");
+        for build in turn.builds.clone().into_iter().rev() {
+            if let Move::Build { card, from } = build {
+                self.unbuild(card, from);
+            }
+        }
+
+        match action {
+            Move::Free { card, from } => {
+                self.free_cell = None;
+                self.tableau[from].push(card);
+            }
+
+            Move::Unfree { card, to } => {
+                self.tableau[to].pop();
+                self.free_cell = Some(card);
+            }
+
+            Move::Build { .. } => unreachable!("Cards are only built automatically"),
+
+            Move::Stack { card, from, to } => {
+                self.tableau[to].pop();
+                self.tableau[from].push(card);
+            }
+        }
+        self.history.pop();
+    }
 }
 
 impl Display for FortunesFoundation {
@@ -594,7 +932,8 @@ impl Display for FortunesFoundation {
             .max()
             .expect("Tableau should not be fully empty");
 
-        println!(
+        writeln!(
+            f,
             "{}  \u{21CB}  {}          {} {} {} {}\n",
             MaybeTarotCard(self.major_foundations[0].last().map(|a| Major(*a))),
             MaybeTarotCard(self.major_foundations[1].last().map(|a| Major(*a))),
@@ -618,7 +957,7 @@ impl Display for FortunesFoundation {
                     .last()
                     .expect("Minor foundation is never empty")
             ),
-        );
+        )?;
 
         for i in 0..max_col_len {
             let row = format!(
@@ -695,30 +1034,830 @@ impl Display for Move {
     }
 }
 
-pub fn display_moves(moves: &Vec<Move>) {
-    if moves.len() <= 5 {
-        for (i, mv) in moves.iter().enumerate() {
-            println!("{i}: {mv}");
+impl Display for Turn {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.mv)?;
+        if !self.builds.is_empty() {
+            let cards = self
+                .builds
+                .iter()
+                .filter_map(|build| match build {
+                    Move::Build { card, .. } => Some(card.to_string().trim().to_owned()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            write!(f, "\t(built {})", cards.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<char> for Suit {
+    type Error = String;
+
+    fn try_from(value: char) -> Result<Self, Self::Error> {
+        match value.to_ascii_uppercase() {
+            'C' => Ok(Cups),
+            'P' => Ok(Pentacles),
+            'S' => Ok(Swords),
+            'W' => Ok(Wands),
+            _ => Err(format!(
+                "unknown suit {value}, expected one of C, P, S or W"
+            )),
         }
-    } else {
-        let left_col_width = moves
-            .chunks(2)
-            .map(|moves| moves[0].to_string().len())
-            .max()
-            .expect("Vec should have at least one chunk")
-            - 3;
-        for moves in moves.iter().enumerate().collect::<Vec<_>>().chunks(2) {
-            match moves.len() {
-                1 => println!("{}: {}", moves[0].0, moves[0].1),
-                2 => println!(
-                    "{}: {:<left_col_width$} | {}: {}",
-                    moves[0].0,
-                    format!("{}", moves[0].1),
-                    moves[1].0,
-                    moves[1].1,
-                ),
-                _ => unreachable!("Chunk size is 2"),
+    }
+}
+
+impl FromStr for TarotCard {
+    type Err = String;
+
+    /// Major arcana are written as their number, like `0` or `21`,
+    /// and minor arcana as rank and suit, like `10C` or `KW`
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        if input.chars().all(|char| char.is_ascii_digit()) {
+            return match input.parse::<u8>() {
+                Ok(rank) if rank <= 21 => Ok(Major(MajorArcana { rank })),
+                _ => Err(format!("major arcana go from 0 to 21, not {input}")),
+            };
+        }
+
+        let suit_char = input.chars().last().expect("Cards are never empty");
+        let rank = &input[..input.len() - suit_char.len_utf8()];
+        let suit = Suit::try_from(suit_char)?;
+        let rank = Rank::try_from(rank.to_owned())
+            .map_err(|_| format!("unknown rank {rank} in {input}"))?;
+        Ok(Minor(Card { rank, suit }))
+    }
+}
+
+impl FromStr for FortunesFoundation {
+    type Err = ParseError;
+
+    /// Reads the layout printed by `to_layout`:
+    /// ```text
+    /// # Comments and blank lines are skipped
+    /// 10P JP 17 5C KS 3 QW
+    /// -
+    /// free 12
+    /// majors 2 20
+    /// ```
+    /// Each of the 11 columns is one line listing its cards from the bottom up, with `-` for an
+    /// empty column. The `free` line is optional and holds the card in the free cell.
+    ///
+    /// Cards that don't appear anywhere are taken to be on the foundations, so they must be
+    /// the next cards to build on them. The optional `majors` line gives the top card of the
+    /// major arcana foundation built up from 0 and then the one built down from 21, with `-` for
+    /// an empty foundation. Without it the missing major arcana are split between the
+    /// foundations where that's the only way they could have been built, and all go on the
+    /// foundation built up from 0 if every one of them is missing.
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut state = Self::default();
+        let mut columns = 0;
+        let mut seen = HashMap::new();
+        let mut free_cell_line = None;
+        let mut majors: Option<(usize, usize, Option<u8>, Option<u8>)> = None;
+
+        for (line_idx, line) in input.lines().enumerate() {
+            let line_no = line_idx + 1;
+            let mut words = words(line).peekable();
+            let Some(&(first_column, first_word)) = words.peek() else {
+                continue;
             };
+            if first_word.starts_with('#') {
+                continue;
+            }
+
+            if first_word.eq_ignore_ascii_case("majors") {
+                if let Some((previous, ..)) = majors {
+                    return Err(ParseError::at(
+                        line_no,
+                        first_column,
+                        format!(
+                            "the major arcana foundations were already given on line {previous}"
+                        ),
+                    ));
+                }
+                words.next();
+                let mut tops = Vec::new();
+                for (column, word) in words {
+                    let top = match word.parse::<TarotCard>() {
+                        _ if word == "-" => None,
+                        Ok(Major(card)) => Some(card.rank),
+                        Ok(Minor(_)) => {
+                            return Err(ParseError::at(
+                                line_no,
+                                column,
+                                format!("{word} isn't a major arcana"),
+                            ))
+                        }
+                        Err(message) => return Err(ParseError::at(line_no, column, message)),
+                    };
+                    tops.push((column, top));
+                }
+                match tops.as_slice() {
+                    &[(_, up), (_, down)] => majors = Some((line_no, first_column, up, down)),
+                    [_, _, (column, _), ..] => {
+                        return Err(ParseError::at(
+                            line_no,
+                            *column,
+                            "there are only 2 major arcana foundations",
+                        ))
+                    }
+                    _ => {
+                        return Err(ParseError::at(
+                            line_no,
+                            line.chars().count() + 1,
+                            "expected the top cards of both major arcana foundations",
+                        ))
+                    }
+                }
+                continue;
+            }
+
+            let cards = if first_word.eq_ignore_ascii_case("free") {
+                if let Some(previous) = free_cell_line.replace(line_no) {
+                    return Err(ParseError::at(
+                        line_no,
+                        first_column,
+                        format!("the free cell was already given on line {previous}"),
+                    ));
+                }
+                words.next();
+                None
+            } else {
+                if columns == state.tableau.len() {
+                    return Err(ParseError::at(
+                        line_no,
+                        first_column,
+                        format!("there are only {} columns", state.tableau.len()),
+                    ));
+                }
+                columns += 1;
+                if first_word == "-" {
+                    words.next();
+                    if let Some((column, word)) = words.next() {
+                        return Err(ParseError::at(
+                            line_no,
+                            column,
+                            format!("expected nothing after an empty column, found {word}"),
+                        ));
+                    }
+                    continue;
+                }
+                Some(&mut state.tableau[columns - 1])
+            };
+
+            let mut parsed = Vec::new();
+            for (column, word) in words {
+                let card = word
+                    .parse::<TarotCard>()
+                    .map_err(|message| ParseError::at(line_no, column, message))?;
+                if matches!(card, Minor(Card { rank: Ace, .. })) {
+                    return Err(ParseError::at(
+                        line_no,
+                        column,
+                        format!("{word} starts on the foundation"),
+                    ));
+                }
+                if let Some((first_line, first_column)) = seen.insert(card, (line_no, column)) {
+                    return Err(ParseError::at(
+                        line_no,
+                        column,
+                        format!(
+                            "{word} was already dealt at line {first_line}, column {first_column}"
+                        ),
+                    ));
+                }
+                parsed.push((column, card));
+            }
+
+            match cards {
+                Some(cards) => cards.extend(parsed.into_iter().map(|(_, card)| card)),
+                None => match parsed.as_slice() {
+                    [(_, card)] => state.free_cell = Some(*card),
+                    [] => {
+                        return Err(ParseError::at(
+                            line_no,
+                            line.chars().count() + 1,
+                            "expected the card in the free cell",
+                        ))
+                    }
+                    [_, (column, _), ..] => {
+                        return Err(ParseError::at(
+                            line_no,
+                            *column,
+                            "the free cell only holds one card",
+                        ))
+                    }
+                },
+            }
+        }
+
+        if columns != state.tableau.len() {
+            return Err(ParseError::new(format!(
+                "expected {} columns, found {columns}",
+                state.tableau.len()
+            )));
+        }
+
+        // Whatever wasn't dealt has already been built
+        for foundation in state.minor_foundations.iter_mut() {
+            let suit = foundation[0].suit;
+            let mut blocker = None;
+            for rank in Rank::iter().skip(1) {
+                let dealt = seen.contains_key(&Minor(Card { rank, suit }));
+                match blocker {
+                    None if dealt => blocker = Some(rank),
+                    None => foundation.push(Card { rank, suit }),
+                    Some(blocker) if !dealt => {
+                        return Err(ParseError::new(format!(
+                        "{rank}{suit} is missing, but can't have been built before {blocker}{suit}"
+                    )))
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+        let is_missing = |rank: &u8| !seen.contains_key(&Major(MajorArcana { rank: *rank }));
+        let (up, down) = match majors {
+            Some((line_no, column, up_top, down_top)) => {
+                let up = up_top.map_or(0..0, |top| 0..top + 1);
+                let down = down_top.map_or(22..22, |top| top..22);
+                if up.end > down.start {
+                    return Err(ParseError::at(
+                        line_no,
+                        column,
+                        "the major arcana foundations overlap",
+                    ));
+                }
+                if let Some(rank) = up
+                    .clone()
+                    .chain(down.clone())
+                    .find(|rank| !is_missing(rank))
+                {
+                    return Err(ParseError::at(
+                        line_no,
+                        column,
+                        format!("major arcana {rank} is on a foundation, but was also dealt"),
+                    ));
+                }
+                (up.collect::<Vec<_>>(), down.rev().collect::<Vec<_>>())
+            }
+            None => {
+                let up = (0..=21).take_while(is_missing).collect::<Vec<_>>();
+                let down = (0..=21)
+                    .rev()
+                    .take_while(|rank| is_missing(rank) && !up.contains(rank))
+                    .collect::<Vec<_>>();
+                (up, down)
+            }
+        };
+        if let Some(rank) =
+            (0..=21).find(|rank| is_missing(rank) && !up.contains(rank) && !down.contains(rank))
+        {
+            return Err(ParseError::new(format!(
+                "major arcana {rank} is missing, but can't have been built"
+            )));
+        }
+        state.major_foundations =
+            [up, down].map(|ranks| ranks.into_iter().map(|rank| MajorArcana { rank }).collect());
+
+        state.auto_build();
+        Ok(state)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+
+    use crate::astar::astar;
+    use crate::fortune::TarotCard::{Major, Minor};
+    use crate::fortune::{Card, FortunesFoundation, MajorArcana, Move, Suit};
+    use crate::game::{Deal, DealError, MoveError, ParseError, Rank, Reversible, State};
+    use crate::search::SearchBudget;
+    use crate::states::{fortune_1, FORTUNE_PRESETS};
+    use crate::testing::{nearly_sorted_fortune, sorted_fortune};
+
+    #[test]
+    fn test_undo() {
+        for state in [fortune_1(), nearly_sorted_fortune()] {
+            let mut in_place = state.clone();
+            for mv in state.possible_actions() {
+                let undo = in_place.apply_in_place(&mv);
+                assert_eq!(in_place, state.act(&mv));
+
+                in_place.undo(undo);
+                assert_eq!(in_place, state);
+                assert!(in_place.history.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn test_try_act() {
+        let state = fortune_1();
+        for mv in state.possible_actions() {
+            assert_eq!(
+                state.try_act(&mv).expect("Generated moves should be legal"),
+                state.act(&mv)
+            );
+        }
+
+        let card = *state.tableau[0].last().expect("Deal has no empty columns");
+        assert_eq!(
+            state.try_act(&Move::Build { card, from: 0 }),
+            Err(MoveError::ManualBuild)
+        );
+        assert_eq!(
+            state.try_act(&Move::Stack {
+                card,
+                from: 0,
+                to: 11
+            }),
+            Err(MoveError::NoSuchColumn { column: 11 })
+        );
+        assert_eq!(
+            state.try_act(&Move::Stack {
+                card,
+                from: 0,
+                to: 0
+            }),
+            Err(MoveError::SameColumn { column: 0 })
+        );
+        assert_eq!(
+            state.try_act(&Move::Unfree { card, to: 0 }),
+            Err(MoveError::FreeCellEmpty)
+        );
+        let other_card = *state.tableau[1].last().expect("Deal has no empty columns");
+        assert!(matches!(
+            state.try_act(&Move::Free {
+                card: other_card,
+                from: 0
+            }),
+            Err(MoveError::NotOnTop { column: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_auto_build_on_deal() {
+        assert!(sorted_fortune().is_win());
+
+        // Everything but the blocked wands is built straight away
+        let state = nearly_sorted_fortune();
+        assert_eq!(state.tableau.iter().flatten().count(), 12);
+        assert_eq!(state.cost_to_go(), 1);
+    }
+
+    #[test]
+    fn test_auto_build_cascade() {
+        let state = nearly_sorted_fortune();
+        let mv = state
+            .possible_actions()
+            .into_iter()
+            .find(|mv| matches!(mv, Move::Stack { to, .. } if state.tableau[*to].is_empty()))
+            .expect("3 of wands should be able to move to an empty column");
+
+        // Moving the 3 uncovers the 2, and building the 2 lets every other wand follow it
+        let mut in_place = state.clone();
+        let undo = in_place.apply_in_place(&mv);
+        assert!(in_place.is_win());
+        let turn = in_place.history.last().expect("Move should be in history");
+        assert_eq!(turn.mv, mv);
+        assert_eq!(turn.builds.len(), 12);
+
+        in_place.undo(undo);
+        assert_eq!(in_place, state);
+        assert_eq!(in_place.tableau, state.tableau);
+    }
+
+    #[test]
+    fn test_no_build_moves() {
+        let state = fortune_1();
+        assert!(!state
+            .possible_actions()
+            .iter()
+            .any(|mv| matches!(mv, Move::Build { .. })));
+    }
+
+    #[test]
+    fn test_redundant_moves() {
+        let major = |rank| Major(MajorArcana { rank });
+        let five = major(5);
+        let cups = |rank| {
+            Minor(Card {
+                rank,
+                suit: Suit::Cups,
+            })
+        };
+        // Neither can build while the cups foundation only has the ace
+        let five_of_cups = cups(Rank::Five);
+        let mut tableau: [Vec<_>; 11] = Default::default();
+        tableau[0] = vec![major(1), five];
+        tableau[1] = vec![cups(Rank::Six), five_of_cups];
+        tableau[2] = vec![major(0)];
+        let state = FortunesFoundation::new(tableau);
+
+        // Parking the 5 lets the 1 build, so putting it back isn't the same as not moving
+        let park = Move::Free {
+            card: five,
+            from: 0,
+        };
+        let parked = state.act(&park);
+        assert_eq!(parked.tableau[0], vec![]);
+        let put_back = Move::Unfree { card: five, to: 0 };
+        assert!(put_back.undoes(&park));
+        assert!(parked.actions_after(None).contains(&put_back));
+
+        // Nothing builds from under the 5 of cups, so putting it straight back is skipped
+        let free = Move::Free {
+            card: five_of_cups,
+            from: 1,
+        };
+        let freed = state.act(&free);
+        let unfree = Move::Unfree {
+            card: five_of_cups,
+            to: 1,
+        };
+        assert!(freed.possible_actions().contains(&unfree));
+        assert!(!freed.actions_after(None).contains(&unfree));
+    }
+
+    fn hash_of(state: &FortunesFoundation) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        state.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Moves the column at index `i` to index `(i + 8) % 11`
+    fn rotate(state: &FortunesFoundation) -> FortunesFoundation {
+        let mut rotated = state.clone();
+        rotated.tableau.rotate_left(3);
+        rotated
+    }
+
+    fn rotate_move(mv: Move) -> Move {
+        let rotate_idx = |idx: usize| (idx + 8) % 11;
+        match mv {
+            Move::Stack { card, from, to } => Move::Stack {
+                card,
+                from: rotate_idx(from),
+                to: rotate_idx(to),
+            },
+            Move::Build { card, from } => Move::Build {
+                card,
+                from: rotate_idx(from),
+            },
+            Move::Free { card, from } => Move::Free {
+                card,
+                from: rotate_idx(from),
+            },
+            Move::Unfree { card, to } => Move::Unfree {
+                card,
+                to: rotate_idx(to),
+            },
+        }
+    }
+
+    #[test]
+    fn test_column_permutation_equality() {
+        let state = fortune_1();
+        let rotated = rotate(&state);
+        assert_ne!(state.tableau, rotated.tableau);
+        assert_eq!(state, rotated);
+        assert_eq!(hash_of(&state), hash_of(&rotated));
+
+        // Same columns, but a card moved between them
+        let mv = state
+            .possible_actions()
+            .into_iter()
+            .find(|mv| matches!(mv, Move::Stack { .. }))
+            .expect("Deal should have a stack move");
+        assert_ne!(state, state.act(&mv));
+    }
+
+    #[test]
+    fn test_column_permutation_moves() {
+        let state = fortune_1();
+        let rotated = rotate(&state);
+        let moves = state
+            .possible_actions()
+            .into_iter()
+            .map(rotate_move)
+            .collect::<Vec<_>>();
+        assert_eq!(moves, rotated.possible_actions());
+    }
+
+    #[test]
+    fn test_column_permutation_solution() {
+        // The path is found on the rotated deal, so it has to use the rotated indices
+        let mut state = rotate(&nearly_sorted_fortune());
+        let path = astar(state.clone(), &SearchBudget::default(), &mut ())
+            .outcome
+            .into_path()
+            .expect("Deal should be solvable");
+        for mv in &path {
+            state = state.act(mv);
+        }
+        assert!(state.is_win());
+    }
+
+    #[test]
+    fn test_layout_round_trip() {
+        let deals = FORTUNE_PRESETS
+            .iter()
+            .map(|(_, deal)| deal())
+            .chain([nearly_sorted_fortune(), sorted_fortune()]);
+        for state in deals {
+            let layout = state.to_layout();
+            let parsed = layout.parse::<FortunesFoundation>().unwrap();
+            assert_eq!(parsed, state, "{layout}");
+            assert_eq!(parsed.to_layout(), layout);
         }
     }
+
+    #[test]
+    fn test_split_majors_round_trip() {
+        // Every major arcana is built, so only the layout's `majors` line says where they meet
+        let mut state = sorted_fortune();
+        state.major_foundations = [
+            (0..=9).map(|rank| MajorArcana { rank }).collect(),
+            (10..=21).rev().map(|rank| MajorArcana { rank }).collect(),
+        ];
+        let layout = state.to_layout();
+        assert!(layout.ends_with("majors 9 10\n"), "{layout}");
+        let parsed = layout.parse::<FortunesFoundation>().unwrap();
+        assert_eq!(parsed.major_foundations, state.major_foundations);
+        assert_eq!(parsed, state);
+
+        // Partly built foundations are written the same way
+        let layout = format!("6 8 7\n{}majors 5 9\n", "-\n".repeat(10));
+        let parsed = layout.parse::<FortunesFoundation>().unwrap();
+        assert_eq!(parsed.major_foundations[0].len(), 6);
+        assert_eq!(parsed.major_foundations[1].len(), 13);
+        assert_eq!(parsed.to_layout(), layout);
+    }
+
+    #[test]
+    fn test_parse_layout() {
+        // Everything else has been built, so the kings just need to get out of the way
+        let layout = "# two moves from a win\nQW KW\nQS\n-\n-\n-\n-\n-\n-\n-\n-\n-\nfree KS\n";
+        let state = layout.parse::<FortunesFoundation>().unwrap();
+        assert_eq!(
+            state.free_cell.map(|card| card.to_string()),
+            Some(" KS".to_owned())
+        );
+        assert_eq!(state.tableau[0].len(), 2);
+        assert_eq!(state.tableau[1].len(), 1);
+        assert!(state.tableau[2..].iter().all(|column| column.is_empty()));
+        assert_eq!(state.major_foundations[0].len(), 22);
+        assert_eq!(
+            astar(state, &SearchBudget::default(), &mut ())
+                .outcome
+                .into_path()
+                .map(|path| path.len()),
+            Some(2),
+            "QS is built as soon as the free cell is empty"
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let empty = "-\n".repeat(10);
+        let cases = [
+            (
+                "1 2 3X\n",
+                ParseError::at(1, 5, "unknown suit X, expected one of C, P, S or W"),
+            ),
+            (
+                "\n  22\n",
+                ParseError::at(2, 3, "major arcana go from 0 to 21, not 22"),
+            ),
+            ("ZW\n", ParseError::at(1, 1, "unknown rank Z in ZW")),
+            ("AC\n", ParseError::at(1, 1, "AC starts on the foundation")),
+            (
+                "5 6\n7 5\n",
+                ParseError::at(2, 3, "5 was already dealt at line 1, column 1"),
+            ),
+            (
+                "- 5\n",
+                ParseError::at(1, 3, "expected nothing after an empty column, found 5"),
+            ),
+        ];
+        for (input, error) in cases {
+            let input = format!("{input}{empty}");
+            assert_eq!(input.parse::<FortunesFoundation>().err(), Some(error));
+        }
+
+        let majors_cases = [
+            (
+                "majors 3 2",
+                ParseError::at(12, 1, "the major arcana foundations overlap"),
+            ),
+            (
+                "majors 10 11",
+                ParseError::at(
+                    12,
+                    1,
+                    "major arcana 10 is on a foundation, but was also dealt",
+                ),
+            ),
+            (
+                "majors KW -",
+                ParseError::at(12, 8, "KW isn't a major arcana"),
+            ),
+            (
+                "majors 3",
+                ParseError::at(
+                    12,
+                    9,
+                    "expected the top cards of both major arcana foundations",
+                ),
+            ),
+            (
+                "majors 1 2 3",
+                ParseError::at(12, 12, "there are only 2 major arcana foundations"),
+            ),
+        ];
+        for (majors, error) in majors_cases {
+            let input = format!("10\n{empty}{majors}\n");
+            assert_eq!(input.parse::<FortunesFoundation>().err(), Some(error));
+        }
+        assert_eq!(
+            format!("{empty}-\nmajors - -\nmajors - -\n")
+                .parse::<FortunesFoundation>()
+                .err(),
+            Some(ParseError::at(
+                13,
+                1,
+                "the major arcana foundations were already given on line 12"
+            ))
+        );
+
+        assert_eq!(
+            "-\n".parse::<FortunesFoundation>().err(),
+            Some(ParseError::new("expected 11 columns, found 1"))
+        );
+        assert_eq!(
+            format!("{empty}-\n-\n").parse::<FortunesFoundation>().err(),
+            Some(ParseError::at(12, 1, "there are only 11 columns"))
+        );
+        assert_eq!(
+            format!("{empty}-\nfree 1 2\n")
+                .parse::<FortunesFoundation>()
+                .err(),
+            Some(ParseError::at(12, 8, "the free cell only holds one card"))
+        );
+        assert_eq!(
+            format!("{empty}-\nfree\n")
+                .parse::<FortunesFoundation>()
+                .err(),
+            Some(ParseError::at(12, 5, "expected the card in the free cell"))
+        );
+        assert_eq!(
+            format!("5W\n{empty}").parse::<FortunesFoundation>().err(),
+            Some(ParseError::new(
+                "6W is missing, but can't have been built before 5W"
+            ))
+        );
+        assert_eq!(
+            format!("3 5\n{empty}").parse::<FortunesFoundation>().err(),
+            Some(ParseError::new(
+                "major arcana 4 is missing, but can't have been built"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        for (name, deal) in FORTUNE_PRESETS {
+            let mut state = deal();
+            for _ in 0..3 {
+                let mv = state.possible_actions()[0];
+                state = state.act(&mv);
+            }
+            for state in [deal(), state] {
+                let json = serde_json::to_string(&state).unwrap();
+                let parsed = serde_json::from_str::<FortunesFoundation>(&json).unwrap();
+                assert_eq!(parsed, state, "{name}");
+                assert_eq!(parsed.history.to_vec(), state.history.to_vec(), "{name}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_json_schema() {
+        let card = Minor(Card {
+            rank: Rank::Ten,
+            suit: Suit::Cups,
+        });
+        let mv = Move::Stack {
+            card,
+            from: 0,
+            to: 3,
+        };
+        assert_eq!(
+            serde_json::to_string(&mv).unwrap(),
+            r#"{"Stack":{"card":{"Minor":{"rank":"Ten","suit":"Cups"}},"from":0,"to":3}}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&Major(MajorArcana { rank: 17 })).unwrap(),
+            r#"{"Major":{"rank":17}}"#
+        );
+
+        // The history can be left out of hand-written games
+        let mut json = serde_json::to_value(nearly_sorted_fortune()).unwrap();
+        json.as_object_mut().unwrap().remove("history");
+        let state = serde_json::from_value::<FortunesFoundation>(json).unwrap();
+        assert_eq!(state, nearly_sorted_fortune());
+
+        // Games are validated as they're read
+        let mut json = serde_json::to_value(FortunesFoundation::deal(0)).unwrap();
+        let cards = json["minor_foundations"][0].take();
+        json["minor_foundations"][0] = serde_json::json!([]);
+        let cards = cards.as_array().unwrap().iter();
+        json["tableau"][5] = cards
+            .map(|card| serde_json::json!({ "Minor": card }))
+            .collect();
+        let err = serde_json::from_value::<FortunesFoundation>(json).unwrap_err();
+        assert_eq!(err.to_string(), "AP must start exactly one foundation");
+    }
+
+    #[test]
+    fn test_deal() {
+        for seed in 0..20 {
+            let state = FortunesFoundation::deal(seed);
+            assert_eq!(state.tableau, FortunesFoundation::deal(seed).tableau);
+            assert_ne!(state, FortunesFoundation::deal(seed + 100));
+
+            // Cards can only have left the columns by being built
+            assert!(state.tableau[5].is_empty());
+            assert!(state.tableau.iter().all(|column| column.len() <= 7));
+            let built = state
+                .minor_foundations
+                .iter()
+                .map(|f| f.len() - 1)
+                .sum::<usize>()
+                + state.major_foundations.iter().map(Vec::len).sum::<usize>();
+            let dealt = state.tableau.iter().map(Vec::len).sum::<usize>();
+            assert_eq!(built + dealt, 70);
+
+            // Parsing checks that every card is either dealt or built, exactly once
+            let parsed = state.to_layout().parse::<FortunesFoundation>().unwrap();
+            assert_eq!(parsed, state);
+            assert_eq!(state.validate(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        for (name, preset) in FORTUNE_PRESETS {
+            assert_eq!(preset().validate(), Ok(()), "{name}");
+        }
+
+        let mut state = sorted_fortune();
+        state.major_foundations[0].swap(3, 4);
+        assert_eq!(
+            state.validate(),
+            Err(DealError::OutOfOrder {
+                card: "4".to_owned()
+            })
+        );
+
+        let mut state = fortune_1();
+        let card = state.tableau[0].pop().unwrap();
+        assert_eq!(
+            state.validate(),
+            Err(DealError::WrongCardCount {
+                expected: 74,
+                found: 73
+            })
+        );
+        state.tableau[1].push(card);
+        state.tableau[2].push(card);
+        assert_eq!(
+            state.validate(),
+            Err(DealError::Duplicate {
+                card: card.to_string().trim().to_owned()
+            })
+        );
+
+        // A foundation emptied onto the tableau would leave nothing to build its suit on
+        let mut state = FortunesFoundation::deal(0);
+        let cards = std::mem::take(&mut state.minor_foundations[0]);
+        state.tableau[5].extend(cards.iter().map(|&card| Minor(card)));
+        let missing = Err(DealError::MissingFoundation {
+            card: Minor(cards[0]).to_string().trim().to_owned(),
+        });
+        assert_eq!(state.validate(), missing);
+
+        let mut state = FortunesFoundation::deal(0);
+        state.minor_foundations[1] = state.minor_foundations[0].clone();
+        assert_eq!(
+            state.validate(),
+            Err(DealError::MissingFoundation {
+                card: "AC".to_owned()
+            })
+        );
+    }
 }
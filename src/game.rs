@@ -1,7 +1,14 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
 use std::fmt::{Debug, Display, Formatter};
-use std::hash::Hash;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::str::FromStr;
+use std::sync::Arc;
 
-use serde::{Deserialize, Serialize};
+use clap::ValueEnum;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use strum::{EnumIter, IntoEnumIterator};
 
 use Rank::{Ace, Jack, King, Queen};
@@ -9,7 +16,9 @@ use Rank::{Ace, Jack, King, Queen};
 use crate::game::Rank::{Eight, Five, Four, Nine, Seven, Six, Ten, Three, Two};
 use crate::game::Suit::{Club, Diamond, Heart, Spade};
 
-#[derive(EnumIter, Debug, Copy, Clone, Eq, Hash, PartialEq, Serialize, Deserialize)]
+#[derive(
+    EnumIter, Debug, Copy, Clone, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize,
+)]
 pub enum Suit {
     Heart,
     Diamond,
@@ -22,10 +31,11 @@ impl TryFrom<String> for Suit {
 
     fn try_from(value: String) -> Result<Self, Self::Error> {
         match value.to_uppercase().as_str() {
-            "H" => Ok(Heart),
-            "D" => Ok(Diamond),
-            "C" => Ok(Club),
-            "S" => Ok(Spade),
+            // The symbols are the ones `Display` prints, plus their filled or hollow twins
+            "H" | "\u{2665}" | "\u{2661}" => Ok(Heart),
+            "D" | "\u{2666}" | "\u{2662}" => Ok(Diamond),
+            "C" | "\u{2667}" | "\u{2663}" => Ok(Club),
+            "S" | "\u{2664}" | "\u{2660}" => Ok(Spade),
             _ => Err(format!("Could not find conversion case for input {value}")),
         }
     }
@@ -43,7 +53,9 @@ impl Display for Suit {
     }
 }
 
-#[derive(EnumIter, Debug, Copy, Clone, Eq, Hash, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[derive(
+    EnumIter, Debug, Copy, Clone, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize,
+)]
 pub enum Rank {
     Ace = 1,
     Two = 2,
@@ -102,7 +114,9 @@ pub enum Color {
     Black,
 }
 
-#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq, Serialize, Deserialize)]
+/// Serialized as `{"suit": "Heart", "rank": "Ten"}`, with the suit and rank named as in `Suit`
+/// and `Rank`
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Card {
     pub suit: Suit,
     pub rank: Rank,
@@ -156,6 +170,103 @@ impl Display for Card {
     }
 }
 
+impl FromStr for Card {
+    type Err = String;
+
+    /// Reads a card written as rank and suit, like `10H` or `K♤`
+    fn from_str(word: &str) -> Result<Self, Self::Err> {
+        let suit_char = word.chars().last().ok_or("expected a card")?;
+        let rank = &word[..word.len() - suit_char.len_utf8()];
+        let suit = Suit::try_from(suit_char.to_string())
+            .map_err(|_| format!("unknown suit {suit_char} in {word}"))?;
+        let rank = Rank::try_from(rank.to_owned())
+            .map_err(|_| format!("unknown rank {rank} in {word}"))?;
+        Ok(Card { rank, suit })
+    }
+}
+
+/// Reads one line of a tableau printed as a grid with 4 characters per column, after `row` lines
+/// of it have been read. Cards can be left or right aligned in their column, and columns may end
+/// early but can't have gaps.
+/// Returns the cards that were added along with the text column each one starts at.
+pub fn parse_grid_row<const N: usize>(
+    tableau: &mut [Vec<Card>; N],
+    row: usize,
+    line_no: usize,
+    line: &str,
+) -> Result<Vec<(usize, Card)>, ParseError> {
+    let mut cards = Vec::new();
+    for (column, word) in words(line) {
+        // The column a card belongs to is the one its last character falls in
+        let last_char = column + word.chars().count() - 2;
+        let idx = last_char / 4;
+        if idx >= N {
+            return Err(ParseError::at(
+                line_no,
+                column,
+                format!("there are only {N} columns"),
+            ));
+        }
+        match tableau[idx].len().cmp(&row) {
+            Ordering::Less => {
+                return Err(ParseError::at(
+                    line_no,
+                    column,
+                    format!("column {} has a gap above {word}", idx + 1),
+                ))
+            }
+            Ordering::Greater => {
+                return Err(ParseError::at(
+                    line_no,
+                    column,
+                    format!("{word} overlaps another card in column {}", idx + 1),
+                ))
+            }
+            Ordering::Equal => {}
+        }
+        let card = word
+            .parse::<Card>()
+            .map_err(|message| ParseError::at(line_no, column, message))?;
+        tableau[idx].push(card);
+        cards.push((column, card));
+    }
+    Ok(cards)
+}
+
+/// Shuffles `items` the same way for a given seed on every platform and version, so that deals
+/// can be shared by their seed.
+/// `SliceRandom::shuffle` doesn't promise that, so this only relies on the raw output of ChaCha8,
+/// which is fixed by its specification.
+pub fn seeded_shuffle<T>(items: &mut [T], seed: u64) {
+    let mut key = [0; 32];
+    key[..8].copy_from_slice(&seed.to_le_bytes());
+    let mut rng = ChaCha8Rng::from_seed(key);
+
+    // Fisher-Yates, rejecting the values past the last whole multiple of the bound so that
+    // every position is equally likely
+    for i in (1..items.len()).rev() {
+        let bound = i as u64 + 1;
+        let limit = u64::MAX - u64::MAX % bound;
+        let j = loop {
+            let value = rng.next_u64();
+            if value < limit {
+                break value % bound;
+            }
+        };
+        items.swap(i, j as usize);
+    }
+}
+
+/// Column indices sorted by the columns' contents.
+/// Visiting columns in this order makes a tableau look the same no matter how it's permuted,
+/// which lets games whose column positions don't matter hash and compare them as a multiset.
+pub fn canonical_order<T: Ord, const N: usize>(tableau: &[Vec<T>; N]) -> [usize; N] {
+    let mut order: [usize; N] = std::array::from_fn(|idx| idx);
+    // Stable sort, so that identical (i.e. empty) columns keep their relative order
+    order.sort_by_key(|&idx| &tableau[idx]);
+    order
+}
+
 pub struct MaybeCard(pub Option<Card>);
 
 impl Display for MaybeCard {
@@ -168,6 +279,144 @@ impl Display for MaybeCard {
     }
 }
 
+struct HistoryNode<T> {
+    item: T,
+    prev: History<T>,
+}
+
+/// Persistent list of the moves that led to a state.
+/// States that share a line of play share the same nodes, so adding a move is O(1) and doesn't
+/// copy the moves that came before it.
+pub struct History<T> {
+    head: Option<Arc<HistoryNode<T>>>,
+    len: usize,
+}
+
+impl<T> History<T> {
+    pub fn new() -> Self {
+        Self { head: None, len: 0 }
+    }
+
+    /// Returns a new history with `item` added as the most recent entry
+    pub fn with(&self, item: T) -> Self {
+        Self {
+            head: Some(Arc::new(HistoryNode {
+                item,
+                prev: self.clone(),
+            })),
+            len: self.len + 1,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes the most recent entry
+    pub fn pop(&mut self) {
+        if let Some(node) = self.head.take() {
+            *self = node.prev.clone();
+        }
+    }
+
+    /// The most recent entry
+    pub fn last(&self) -> Option<&T> {
+        self.head.as_ref().map(|node| &node.item)
+    }
+
+    /// Iterates from the most recent entry back to the first one
+    pub fn recent(&self) -> impl Iterator<Item = &T> {
+        let mut current = self.head.as_deref();
+        std::iter::from_fn(move || {
+            let node = current?;
+            current = node.prev.head.as_deref();
+            Some(&node.item)
+        })
+    }
+
+    /// Collects the entries in the order they were added
+    pub fn to_vec(&self) -> Vec<T>
+    where
+        T: Clone,
+    {
+        let mut items = self.recent().cloned().collect::<Vec<_>>();
+        items.reverse();
+        items
+    }
+}
+
+impl<T> Default for History<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for History<T> {
+    fn clone(&self) -> Self {
+        Self {
+            head: self.head.clone(),
+            len: self.len,
+        }
+    }
+}
+
+impl<T> Drop for History<T> {
+    fn drop(&mut self) {
+        // Dropping the nodes recursively could overflow the stack for long games, so we unlink
+        // them one at a time until we reach a node that's still shared with another history
+        let mut current = self.head.take();
+        while let Some(node) = current {
+            match Arc::try_unwrap(node) {
+                Ok(mut node) => current = node.prev.head.take(),
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+impl<T: PartialEq> PartialEq for History<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.recent().eq(other.recent())
+    }
+}
+
+impl<T: Eq> Eq for History<T> {}
+
+impl<T: Hash> Hash for History<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len.hash(state);
+        for item in self.recent() {
+            item.hash(state);
+        }
+    }
+}
+
+impl<T: Debug + Clone> Debug for History<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.to_vec()).finish()
+    }
+}
+
+impl<T: Serialize + Clone> Serialize for History<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.to_vec())
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for History<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let items = Vec::<T>::deserialize(deserializer)?;
+        Ok(items
+            .into_iter()
+            .fold(History::new(), |history, item| history.with(item)))
+    }
+}
+
 pub enum EndState {
     Win,
     Loss,
@@ -179,14 +428,499 @@ impl EndState {
     }
 }
 
-pub trait State: Eq + Hash {
-    type Action: Clone + Debug;
+/// Why a move can't be made from the current position.
+/// Columns are stored as indices, but shown counting from 1.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MoveError {
+    /// The game has already been won or lost
+    GameOver,
+    NoSuchColumn {
+        column: usize,
+    },
+    EmptyColumn {
+        column: usize,
+    },
+    /// The moved cards aren't the ones on top of the column they're moved from
+    NotOnTop {
+        cards: String,
+        column: usize,
+    },
+    /// The moved cards don't form a run that can be moved together
+    NotARun {
+        cards: String,
+    },
+    CannotStack {
+        cards: String,
+        column: usize,
+    },
+    SameColumn {
+        column: usize,
+    },
+    /// A completed stack of face cards in Proletariat's Patience can't be moved again
+    CompletedStack {
+        column: usize,
+    },
+    FreeCellOccupied,
+    FreeCellEmpty,
+    NotInFreeCell {
+        card: String,
+    },
+    /// Fortune's Foundation builds cards by itself, so a build can't be played as a move
+    ManualBuild,
+    /// Playing the card would take the cribbage stack over 31
+    StackOverflow {
+        card: String,
+        total: u8,
+    },
+    /// A new cribbage stack can only be started once no card can be played
+    StackNotFinished,
+    /// The move isn't one of the moves available in this position
+    NotAvailable,
+}
+
+impl Display for MoveError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MoveError::GameOver => write!(f, "the game is already over"),
+            MoveError::NoSuchColumn { column } => write!(f, "there is no column {}", column + 1),
+            MoveError::EmptyColumn { column } => write!(f, "column {} is empty", column + 1),
+            MoveError::NotOnTop { cards, column } => {
+                write!(f, "{cards} is not on top of column {}", column + 1)
+            }
+            MoveError::NotARun { cards } => write!(f, "{cards} can't be moved together"),
+            MoveError::CannotStack { cards, column } => {
+                write!(f, "{cards} can't be placed on column {}", column + 1)
+            }
+            MoveError::SameColumn { column } => {
+                write!(f, "can't move cards from column {} to itself", column + 1)
+            }
+            MoveError::CompletedStack { column } => {
+                write!(
+                    f,
+                    "column {} is a completed stack and can't be moved",
+                    column + 1
+                )
+            }
+            MoveError::FreeCellOccupied => write!(f, "the free cell is already occupied"),
+            MoveError::FreeCellEmpty => write!(f, "the free cell is empty"),
+            MoveError::NotInFreeCell { card } => write!(f, "{card} is not in the free cell"),
+            MoveError::ManualBuild => write!(f, "cards are built to the foundations automatically"),
+            MoveError::StackOverflow { card, total } => {
+                write!(
+                    f,
+                    "playing {card} would bring the stack to {total}, over 31"
+                )
+            }
+            MoveError::StackNotFinished => {
+                write!(
+                    f,
+                    "a new stack can only be started when no card can be played"
+                )
+            }
+            MoveError::NotAvailable => write!(f, "that move isn't available"),
+        }
+    }
+}
+
+impl std::error::Error for MoveError {}
+
+/// Why a tableau isn't a valid deal for its game
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DealError {
+    WrongCardCount {
+        expected: usize,
+        found: usize,
+    },
+    Duplicate {
+        card: String,
+    },
+    ExcludedRank {
+        card: String,
+    },
+    /// A card on a foundation that doesn't follow on from the card under it
+    OutOfOrder {
+        card: String,
+    },
+    /// An ace that doesn't start exactly one foundation
+    MissingFoundation {
+        card: String,
+    },
+    StackTotal {
+        total: usize,
+    },
+    /// More points than the cards already played could have scored
+    ScoreTooHigh {
+        score: usize,
+        max: usize,
+    },
+}
+
+impl Display for DealError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DealError::WrongCardCount { expected, found } => {
+                write!(f, "expected {expected} cards, found {found}")
+            }
+            DealError::Duplicate { card } => write!(f, "{card} was dealt more than once"),
+            DealError::ExcludedRank { card } => write!(f, "{card} isn't part of this deck"),
+            DealError::OutOfOrder { card } => write!(f, "{card} is out of order on its foundation"),
+            DealError::MissingFoundation { card } => {
+                write!(f, "{card} must start exactly one foundation")
+            }
+            DealError::StackTotal { total } => {
+                write!(f, "the stack total can't go over 31, but is {total}")
+            }
+            DealError::ScoreTooHigh { score, max } => {
+                write!(f, "the played cards can score at most {max}, not {score}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for DealError {}
+
+/// The games that can be solved, as named on the command line and in saved solutions
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Game {
+    Fortune,
+    Russian,
+    Cribbage,
+}
+
+/// A game whose deals can be read from JSON, which doesn't check the rules of the game like
+/// parsing a layout does
+pub trait Deal {
+    /// Checks that the deal holds the right cards, each once, in places they're allowed to be
+    fn validate(&self) -> Result<(), DealError>;
+}
+
+/// Why a deal couldn't be read from text. Lines and columns count from 1, and are missing for
+/// problems with the deal as a whole, like a card that appears nowhere.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParseError {
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl ParseError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            line: None,
+            column: None,
+            message: message.into(),
+        }
+    }
+
+    pub fn at(line: usize, column: usize, message: impl Into<String>) -> Self {
+        Self {
+            line: Some(line),
+            column: Some(column),
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => {
+                write!(f, "line {line}, column {column}: {}", self.message)
+            }
+            (Some(line), None) => write!(f, "line {line}: {}", self.message),
+            _ => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<DealError> for ParseError {
+    fn from(error: DealError) -> Self {
+        ParseError::new(error.to_string())
+    }
+}
+
+/// Splits a line into whitespace separated words, along with the column each one starts at
+pub fn words(line: &str) -> impl Iterator<Item = (usize, &str)> {
+    line.char_indices()
+        .filter(|&(idx, char)| {
+            !char.is_whitespace()
+                && line[..idx]
+                    .chars()
+                    .next_back()
+                    .is_none_or(char::is_whitespace)
+        })
+        .map(move |(idx, _)| {
+            let word = line[idx..]
+                .split_whitespace()
+                .next()
+                .expect("Word starts at a non-whitespace char");
+            (line[..idx].chars().count() + 1, word)
+        })
+}
+
+/// Games must be `Send + Sync` so that the parallel solvers can share states between threads
+pub trait State: Eq + Hash + Send + Sync {
+    type Action: Clone + Debug + Send + Sync;
 
     fn end_status(&self) -> Option<EndState>;
 
     fn possible_actions(&self) -> Vec<Self::Action>;
 
+    /// Makes a move, which must be legal. Use `try_act` for moves that haven't been checked.
     fn act(&self, action: &Self::Action) -> Self;
 
+    /// Makes a move from an untrusted source like a player or a solution file,
+    /// returning why it's illegal instead of panicking
+    fn try_act(&self, action: &Self::Action) -> Result<Self, MoveError>
+    where
+        Self: Sized;
+
     fn evaluate(&self, print_components: bool) -> f32;
+
+    /// Lower bound on the number of moves needed to win from this state.
+    /// Informed searches rely on this never overestimating, so the default of 0 is always safe.
+    fn cost_to_go(&self) -> usize {
+        0
+    }
+
+    /// Optional hook for skipping moves that are pointless straight after `previous`,
+    /// like moving the card that was just moved straight back.
+    /// Only moves that lead back to a state the search has already seen may be skipped,
+    /// or searches that try every move would no longer be exhaustive.
+    /// This keeps `possible_actions` a pure function of the position, since the solvers pass in
+    /// the previous move from their own search path.
+    fn is_redundant(&self, _previous: &Self::Action, _action: &Self::Action) -> bool {
+        false
+    }
+
+    /// The move that was actually played to reach this state, for games that keep track of it.
+    /// Lets a search starting from a game in progress use `is_redundant` at its root.
+    fn last_action(&self) -> Option<&Self::Action> {
+        None
+    }
+
+    /// Describes `action` for printing a line of play, given the state it led to.
+    /// Games where more happens by itself after a move can say what else happened.
+    fn describe_action(&self, action: &Self::Action) -> String
+    where
+        Self::Action: Display,
+    {
+        action.to_string()
+    }
+
+    /// Bytes the state has allocated on the heap, for solvers that store states and have to keep
+    /// within a memory budget. History shared with other states isn't counted.
+    fn heap_size(&self) -> usize {
+        0
+    }
+
+    /// Actions worth searching from this state, given the move that led to it on the search path.
+    /// Falls back to `last_action` at the root of a search.
+    fn actions_after(&self, previous: Option<&Self::Action>) -> Vec<Self::Action> {
+        let mut actions = self.possible_actions();
+        if let Some(previous) = previous.or(self.last_action()) {
+            actions.retain(|action| !self.is_redundant(previous, action));
+        }
+        actions
+    }
+}
+
+/// Bytes allocated by the vectors in a game's columns or foundations
+pub fn heap_size_of<T>(vecs: &[Vec<T>]) -> usize {
+    vecs.iter().map(|v| v.capacity() * size_of::<T>()).sum()
+}
+
+pub fn hash_state<S: Hash>(state: &S) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    state.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The states on the path a search is currently exploring, so that it can skip moves that lead
+/// back to a position it's already in the middle of searching.
+/// Whole states are kept rather than hashes, since a hash collision would make a search skip a
+/// state it has never seen, and then it couldn't prove anything by searching every state.
+pub struct SearchPath<S> {
+    states: HashSet<S>,
+}
+
+impl<S> Default for SearchPath<S> {
+    fn default() -> Self {
+        Self {
+            states: HashSet::new(),
+        }
+    }
+}
+
+impl<S: Clone + Eq + Hash> SearchPath<S> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a state to the path. Returns `false` if it was already on the path.
+    pub fn enter(&mut self, state: &S) -> bool {
+        !self.states.contains(state) && self.states.insert(state.clone())
+    }
+
+    /// Removes a state added by `enter` once the search backtracks past it
+    pub fn leave(&mut self, state: &S) {
+        self.states.remove(state);
+    }
+
+    pub fn contains(&self, state: &S) -> bool {
+        self.states.contains(state)
+    }
+
+    /// Number of states on the path, including the one the search started from
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+}
+
+/// Prints numbered moves, in two columns if there are more than a few
+pub fn display_moves<M: Display>(moves: &[M]) {
+    if moves.len() <= 5 {
+        for (i, mv) in moves.iter().enumerate() {
+            println!("{i}: {mv}");
+        }
+    } else {
+        let left_col_width = moves
+            .chunks(2)
+            .map(|moves| moves[0].to_string().len())
+            .max()
+            .expect("Vec should have at least one chunk")
+            .saturating_sub(3);
+        for moves in moves.iter().enumerate().collect::<Vec<_>>().chunks(2) {
+            match moves.len() {
+                1 => println!("{}: {}", moves[0].0, moves[0].1),
+                2 => println!(
+                    "{}: {:<left_col_width$} | {}: {}",
+                    moves[0].0,
+                    format!("{}", moves[0].1),
+                    moves[1].0,
+                    moves[1].1,
+                ),
+                _ => unreachable!("Chunk size is 2"),
+            };
+        }
+    }
+}
+
+/// Optional extension of `State` for games that can make and unmake moves in place.
+/// This lets searches walk the tree with a single state instead of allocating one per move.
+pub trait Reversible: State {
+    /// Anything needed to undo a move that can't be recovered from the state after it
+    type Undo;
+
+    fn apply_in_place(&mut self, action: &Self::Action) -> Self::Undo;
+
+    /// Reverts the move that returned `undo`, which must be the last move applied
+    fn undo(&mut self, undo: Self::Undo);
+}
+
+#[cfg(test)]
+mod test {
+    use crate::game::{seeded_shuffle, words, History, SearchPath};
+    use crate::testing::Collides;
+
+    #[test]
+    fn test_seeded_shuffle() {
+        let shuffled = |seed| {
+            let mut items = (0..10).collect::<Vec<_>>();
+            seeded_shuffle(&mut items, seed);
+            items
+        };
+        // Deals are shared by seed, so these must never change
+        assert_eq!(shuffled(0), vec![3, 0, 5, 9, 6, 7, 2, 4, 1, 8]);
+        assert_eq!(shuffled(1), vec![5, 6, 9, 0, 8, 4, 3, 2, 1, 7]);
+        assert_eq!(shuffled(7), shuffled(7));
+        assert_ne!(shuffled(7), shuffled(8));
+    }
+
+    #[test]
+    fn test_words() {
+        assert_eq!(
+            words("  10C\tKW  3 ").collect::<Vec<_>>(),
+            vec![(3, "10C"), (7, "KW"), (11, "3")]
+        );
+        assert_eq!(words("   ").count(), 0);
+    }
+
+    #[test]
+    fn test_history_order() {
+        let history = History::new().with(1).with(2).with(3);
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.last(), Some(&3));
+        assert_eq!(history.recent().copied().collect::<Vec<_>>(), vec![3, 2, 1]);
+        assert_eq!(history.to_vec(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_history_pop() {
+        let mut history = History::new().with(1).with(2);
+        history.pop();
+        assert_eq!(history.to_vec(), vec![1]);
+        history.pop();
+        history.pop();
+        assert!(history.is_empty());
+    }
+
+    #[test]
+    fn test_history_is_persistent() {
+        let base = History::new().with(1).with(2);
+        let left = base.with(3);
+        let right = base.with(4);
+
+        assert_eq!(base.to_vec(), vec![1, 2]);
+        assert_eq!(left.to_vec(), vec![1, 2, 3]);
+        assert_eq!(right.to_vec(), vec![1, 2, 4]);
+        assert_ne!(left, right);
+        assert_eq!(left, History::new().with(1).with(2).with(3));
+    }
+
+    #[test]
+    fn test_history_serde() {
+        let history = History::new().with(1).with(2).with(3);
+        let json = serde_json::to_string(&history).unwrap();
+        assert_eq!(json, "[1,2,3]");
+        assert_eq!(
+            serde_json::from_str::<History<i32>>(&json).unwrap(),
+            history
+        );
+    }
+
+    #[test]
+    fn test_long_history_drop() {
+        let mut history = History::new();
+        for i in 0..1_000_000 {
+            history = history.with(i);
+        }
+        drop(history);
+    }
+
+    #[test]
+    fn test_search_path() {
+        let mut path = SearchPath::new();
+        assert!(path.enter(&1));
+        assert!(path.enter(&2));
+        assert!(!path.enter(&1));
+
+        path.leave(&2);
+        assert!(!path.contains(&2));
+        assert!(path.enter(&2));
+
+        // States with the same hash are still different states
+        let mut path = SearchPath::new();
+        assert!(path.enter(&Collides(1)));
+        assert!(path.enter(&Collides(2)));
+        assert!(!path.contains(&Collides(3)));
+    }
 }
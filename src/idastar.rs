@@ -0,0 +1,426 @@
+use std::hash::Hash;
+use std::mem::size_of;
+
+use crate::game::{hash_state, EndState, SearchPath, State};
+use crate::search::{
+    Meter, SearchBudget, SearchObserver, SearchOutcome, SearchResult, StopReason, Tracker,
+};
+
+#[derive(Clone)]
+struct Entry<S> {
+    key: u64,
+    state: S,
+    cost: usize,
+    iteration: usize,
+}
+
+/// Table of states seen during the current IDA* iteration, which grows as it fills up until it
+/// reaches its memory budget.
+/// Whole states are stored, so that a hash collision can't prune a state that was never searched.
+pub struct TranspositionTable<S> {
+    slots: Vec<Option<Entry<S>>>,
+    /// Number of slots holding an entry, from any iteration
+    filled: usize,
+    /// Bytes taken by the slots and by what the stored states allocate for themselves
+    memory_used: usize,
+    memory_budget: usize,
+    iteration: usize,
+    /// Number of different states searched during the current iteration. A state that didn't
+    /// fit in the table can be counted again if the search comes back to it.
+    searched: usize,
+}
+
+/// Number of slots a table starts with, if its budget allows that many
+const INITIAL_SLOTS: usize = 1024;
+
+impl<S: State + Clone + Eq + Hash> TranspositionTable<S> {
+    /// Creates a table that keeps its slots and the states stored in them within `memory_budget`
+    /// bytes. It always has at least one slot, even if that doesn't fit.
+    pub fn new(memory_budget: usize) -> Self {
+        let slots = (memory_budget / Self::SLOT_SIZE).clamp(1, INITIAL_SLOTS);
+        Self {
+            slots: vec![None; slots],
+            filled: 0,
+            memory_used: slots * Self::SLOT_SIZE,
+            memory_budget,
+            iteration: 0,
+            searched: 0,
+        }
+    }
+
+    const SLOT_SIZE: usize = size_of::<Option<Entry<S>>>();
+
+    #[allow(dead_code)]
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Entries from previous iterations were searched with a lower bound,
+    /// so they can no longer be used for pruning
+    fn next_iteration(&mut self) {
+        self.iteration += 1;
+        self.searched = 0;
+    }
+
+    /// Doubles the number of slots once half of them are filled, if the budget has room for it.
+    /// Entries that land in the same slot afterwards are resolved like any other collision.
+    fn grow(&mut self) {
+        let len = self.slots.len();
+        let extra = len * Self::SLOT_SIZE;
+        if self.filled * 2 < len || self.memory_used + extra > self.memory_budget {
+            return;
+        }
+
+        let old_slots = std::mem::replace(&mut self.slots, vec![None; len * 2]);
+        self.memory_used += extra;
+        self.filled = 0;
+        for entry in old_slots.into_iter().flatten() {
+            let idx = (entry.key % self.slots.len() as u64) as usize;
+            match &self.slots[idx] {
+                Some(other) if Self::keeps(other, entry.iteration, entry.cost) => {
+                    self.memory_used -= entry.state.heap_size();
+                }
+                Some(other) => {
+                    self.memory_used -= other.state.heap_size();
+                    self.slots[idx] = Some(entry);
+                }
+                None => {
+                    self.filled += 1;
+                    self.slots[idx] = Some(entry);
+                }
+            }
+        }
+    }
+
+    /// Whether `entry` should stay in its slot rather than make way for a different state
+    /// reached with `cost` moves during `iteration`
+    fn keeps(entry: &Entry<S>, iteration: usize, cost: usize) -> bool {
+        // Prefer keeping states closer to the root, since pruning them skips bigger subtrees
+        entry.iteration > iteration || (entry.iteration == iteration && entry.cost < cost)
+    }
+
+    /// Returns `true` if this state was already searched this iteration with at most `cost` moves,
+    /// in which case searching it again can't find anything new.
+    /// Otherwise, the state is recorded if the replacement policy and the memory budget allow it.
+    fn seen(&mut self, state: &S, cost: usize) -> bool {
+        let key = hash_state(state);
+        self.grow();
+
+        let idx = (key % self.slots.len() as u64) as usize;
+        let mut same = false;
+        let mut freed = 0;
+        if let Some(entry) = &self.slots[idx] {
+            if entry.iteration == self.iteration {
+                same = entry.key == key && entry.state == *state;
+                if same && entry.cost <= cost {
+                    return true;
+                }
+            }
+            if !same && Self::keeps(entry, self.iteration, cost) {
+                self.searched += 1;
+                return false;
+            }
+            freed = entry.state.heap_size();
+        }
+        // Searching a state again with fewer moves doesn't make it a new one
+        if !same {
+            self.searched += 1;
+        }
+
+        let stored = state.clone();
+        let added = stored.heap_size();
+        // A state that doesn't fit is still searched, it just can't be pruned if it comes up again
+        if added > freed && self.memory_used - freed + added > self.memory_budget {
+            return false;
+        }
+        if self.slots[idx].is_none() {
+            self.filled += 1;
+        }
+        self.memory_used = self.memory_used - freed + added;
+        self.slots[idx] = Some(Entry {
+            key,
+            state: stored,
+            cost,
+            iteration: self.iteration,
+        });
+        false
+    }
+}
+
+enum Outcome {
+    Found,
+    /// The lowest `g + h` that exceeded the bound, or `None` if the whole tree was searched
+    Exceeded(Option<usize>),
+    /// The search ran out of budget
+    Stopped(StopReason),
+}
+
+/// Iterative deepening A*.
+/// Runs repeated depth-first searches, each pruning states whose `g + h` exceeds a bound, and raises
+/// the bound to the lowest pruned value until a win is found.
+/// Like `astar`, this finds a shortest win path if `cost_to_go` never overestimates, but memory use
+/// is capped by `memory_budget` (in bytes) for the transposition table.
+/// The deal is proven unsolvable once an iteration searches every reachable state without
+/// pruning any by the bound.
+pub fn idastar<S: State + Clone>(
+    state: S,
+    memory_budget: usize,
+    budget: &SearchBudget,
+    observer: &mut dyn SearchObserver,
+) -> SearchResult<<S as State>::Action> {
+    fn search<S: State + Clone>(
+        state: &S,
+        path: &mut Vec<<S as State>::Action>,
+        path_states: &mut SearchPath<S>,
+        bound: usize,
+        table: &mut TranspositionTable<S>,
+        tracker: &mut Tracker,
+    ) -> Outcome {
+        let cost = path.len();
+        let estimate = cost + state.cost_to_go();
+        if estimate > bound {
+            return Outcome::Exceeded(Some(estimate));
+        }
+        if let Some(reason) = tracker.out_of_budget() {
+            return Outcome::Stopped(reason);
+        }
+
+        if table.seen(state, cost) {
+            tracker.prune_duplicate();
+            return Outcome::Exceeded(None);
+        }
+        match state.end_status() {
+            Some(EndState::Win) => return Outcome::Found,
+            Some(EndState::Loss) => return Outcome::Exceeded(None),
+            None => (),
+        }
+
+        tracker.expand(cost);
+        let mut next_bound = None;
+        for mv in state.actions_after(path.last()) {
+            let next_state = state.act(&mv);
+            tracker.generate();
+            // The table can lose entries, so cycles back to a state on the current path
+            // have to be caught separately
+            if !path_states.enter(&next_state) {
+                tracker.prune_duplicate();
+                continue;
+            }
+            path.push(mv);
+            let outcome = search(&next_state, path, path_states, bound, table, tracker);
+            path_states.leave(&next_state);
+            match outcome {
+                Outcome::Found => return Outcome::Found,
+                Outcome::Stopped(reason) => return Outcome::Stopped(reason),
+                Outcome::Exceeded(Some(estimate)) => {
+                    next_bound = Some(next_bound.map_or(estimate, |b: usize| b.min(estimate)));
+                }
+                Outcome::Exceeded(None) => (),
+            }
+            path.pop();
+        }
+
+        Outcome::Exceeded(next_bound)
+    }
+
+    let mut tracker = Tracker::new(budget, observer);
+    let mut table = TranspositionTable::new(memory_budget);
+    let mut bound = state.cost_to_go();
+    let mut path = Vec::new();
+    let mut path_states = SearchPath::new();
+    path_states.enter(&state);
+    loop {
+        table.next_iteration();
+        match search(
+            &state,
+            &mut path,
+            &mut path_states,
+            bound,
+            &mut table,
+            &mut tracker,
+        ) {
+            Outcome::Found => return tracker.finish(SearchOutcome::Solved(path)),
+            Outcome::Exceeded(Some(next_bound)) => bound = next_bound,
+            Outcome::Exceeded(None) => {
+                return tracker.finish(SearchOutcome::ProvenUnsolvable {
+                    states_explored: table.searched,
+                })
+            }
+            Outcome::Stopped(reason) => {
+                return tracker.finish(SearchOutcome::Inconclusive { reason })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::game::State;
+    use crate::idastar::{idastar, TranspositionTable};
+    use crate::russian::ProletariatsPatience;
+    use crate::search::{SearchBudget, SearchOutcome, StopReason};
+    use crate::testing::{
+        nearly_solved_russian, nearly_sorted_fortune, Collides, DeadEnd, NumberLine,
+    };
+
+    const MEMORY_BUDGET: usize = 1024 * 1024;
+
+    #[test]
+    fn test_shortest_path() {
+        let path = idastar(
+            NumberLine(0),
+            MEMORY_BUDGET,
+            &SearchBudget::default(),
+            &mut (),
+        )
+        .outcome
+        .into_path()
+        .expect("Number line should be solvable");
+        assert_eq!(path.len(), 4);
+        assert_eq!(path.iter().sum::<i32>(), 10);
+    }
+
+    #[test]
+    fn test_tiny_memory_budget() {
+        // With a single slot, almost nothing gets pruned, but the search is still optimal
+        let path = idastar(NumberLine(0), 0, &SearchBudget::default(), &mut ())
+            .outcome
+            .into_path()
+            .expect("Number line should be solvable");
+        assert_eq!(path.len(), 4);
+    }
+
+    #[test]
+    fn test_russian_shortest_path() {
+        let mut state = nearly_solved_russian();
+        let path = idastar(
+            state.clone(),
+            MEMORY_BUDGET,
+            &SearchBudget::default(),
+            &mut (),
+        )
+        .outcome
+        .into_path()
+        .expect("Deal should be solvable");
+        assert_eq!(path.len(), 2);
+
+        for mv in &path {
+            state = state.act(mv);
+        }
+        assert!(state.is_win());
+    }
+
+    #[test]
+    fn test_fortune_shortest_path() {
+        let mut state = nearly_sorted_fortune();
+        let path = idastar(
+            state.clone(),
+            MEMORY_BUDGET,
+            &SearchBudget::default(),
+            &mut (),
+        )
+        .outcome
+        .into_path()
+        .expect("Deal should be solvable");
+        assert_eq!(path.len(), state.cost_to_go());
+
+        for mv in &path {
+            state = state.act(mv);
+        }
+        assert!(state.is_win());
+    }
+
+    #[test]
+    fn test_unsolvable() {
+        assert!(matches!(
+            idastar(DeadEnd(0), MEMORY_BUDGET, &SearchBudget::default(), &mut ()).outcome,
+            SearchOutcome::ProvenUnsolvable { states_explored: 7 }
+        ));
+        // Losing cached states doesn't stop it from searching everything
+        assert!(matches!(
+            idastar(DeadEnd(0), 0, &SearchBudget::default(), &mut ()).outcome,
+            SearchOutcome::ProvenUnsolvable { .. }
+        ));
+    }
+
+    #[test]
+    fn test_table_replacement() {
+        let mut table = TranspositionTable::new(0);
+        assert_eq!(table.capacity(), 1);
+        table.next_iteration();
+
+        assert!(!table.seen(&NumberLine(5), 3));
+        assert!(table.seen(&NumberLine(5), 3));
+        assert!(table.seen(&NumberLine(5), 4));
+        // Reaching the same state with fewer moves has to be searched again
+        assert!(!table.seen(&NumberLine(5), 2));
+        // A deeper state doesn't replace a shallower one
+        assert!(!table.seen(&NumberLine(6), 5));
+        assert!(table.seen(&NumberLine(5), 2));
+        // But a shallower one does
+        assert!(!table.seen(&NumberLine(6), 1));
+        assert!(!table.seen(&NumberLine(5), 2));
+
+        // Entries from previous iterations are always replaced
+        table.next_iteration();
+        assert!(!table.seen(&NumberLine(5), 2));
+    }
+
+    #[test]
+    fn test_table_collision() {
+        let mut table = TranspositionTable::new(MEMORY_BUDGET);
+        table.next_iteration();
+        assert!(!table.seen(&Collides(1), 1));
+        // Same hash, so the same slot, but a different state that still has to be searched
+        assert!(!table.seen(&Collides(2), 1));
+        assert!(table.seen(&Collides(2), 1));
+        assert_eq!(table.searched, 2);
+    }
+
+    #[test]
+    fn test_table_growth() {
+        let mut table = TranspositionTable::new(MEMORY_BUDGET);
+        let initial = table.capacity();
+        table.next_iteration();
+        assert!(!table.seen(&NumberLine(-1), 0));
+        for n in 0..initial as i32 * 2 {
+            assert!(!table.seen(&NumberLine(n), 1));
+        }
+        assert!(table.capacity() > initial);
+        assert!(table.capacity() * TranspositionTable::<NumberLine>::SLOT_SIZE <= MEMORY_BUDGET);
+        // Growing keeps what was already stored, as long as nothing closer to the root collides
+        assert!(table.seen(&NumberLine(-1), 0));
+    }
+
+    #[test]
+    fn test_table_counts_states() {
+        let state = nearly_solved_russian();
+        let slot_size = TranspositionTable::<ProletariatsPatience>::SLOT_SIZE;
+
+        // Room for the slot, but not for the columns the state allocates
+        let mut table = TranspositionTable::new(slot_size);
+        table.next_iteration();
+        assert!(!table.seen(&state, 1));
+        assert!(!table.seen(&state, 1));
+
+        let mut table = TranspositionTable::new(slot_size + state.heap_size());
+        table.next_iteration();
+        assert!(!table.seen(&state, 1));
+        assert!(table.seen(&state, 1));
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        // The table is allocated once the search has started, so a limit of 0 stops it at once
+        let budget = SearchBudget {
+            max_memory: Some(0),
+            ..SearchBudget::default()
+        };
+        assert_eq!(
+            idastar(NumberLine(0), MEMORY_BUDGET, &budget, &mut ()).outcome,
+            SearchOutcome::Inconclusive {
+                reason: StopReason::MaxMemory
+            }
+        );
+    }
+}
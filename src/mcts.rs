@@ -0,0 +1,339 @@
+use rand::prelude::SliceRandom;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::game::{SearchPath, State};
+use crate::search::{Meter, SearchBudget, SearchObserver, SearchStats, Tracker};
+
+/// How moves are chosen when playing out a game from a newly expanded node
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Rollout {
+    /// Pick uniformly random moves
+    Random,
+    /// Pick the move leading to the highest `evaluate` score, except for a random move with
+    /// probability `epsilon` (in percent)
+    Greedy { epsilon: u8 },
+}
+
+#[derive(Clone, Debug)]
+pub struct MctsConfig {
+    /// Number of select-expand-rollout-backup iterations, or unlimited if `None`
+    pub iterations: Option<usize>,
+    /// Limits for the whole search
+    pub budget: SearchBudget,
+    /// Maximum number of moves played in a single rollout
+    pub rollout_depth: usize,
+    pub rollout: Rollout,
+    /// Weight of the exploration term in UCT
+    pub exploration: f32,
+    pub seed: u64,
+}
+
+impl Default for MctsConfig {
+    fn default() -> Self {
+        Self {
+            iterations: Some(10_000),
+            budget: SearchBudget::default(),
+            rollout_depth: 50,
+            rollout: Rollout::Random,
+            exploration: std::f32::consts::SQRT_2,
+            seed: 0,
+        }
+    }
+}
+
+/// Search results for one of the moves available at the root
+#[derive(Clone, Debug)]
+pub struct ActionStats<A> {
+    pub action: A,
+    pub visits: usize,
+    pub best_reward: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct MctsResult<A> {
+    /// Search results for each of the root moves that was tried
+    pub actions: Vec<ActionStats<A>>,
+    pub stats: SearchStats,
+}
+
+impl<A> MctsResult<A> {
+    /// The move leading to the best outcome found, or `None` if there are no moves to make.
+    /// Ties go to the move that was visited most.
+    pub fn best(&self) -> Option<&A> {
+        self.actions
+            .iter()
+            .max_by(|a, b| {
+                a.best_reward
+                    .total_cmp(&b.best_reward)
+                    .then(a.visits.cmp(&b.visits))
+            })
+            .map(|best| &best.action)
+    }
+}
+
+struct Node<S: State> {
+    state: S,
+    parent: Option<usize>,
+    action: Option<<S as State>::Action>,
+    children: Vec<usize>,
+    /// Moves from the root
+    depth: usize,
+    untried: Vec<<S as State>::Action>,
+    visits: usize,
+    best_reward: f32,
+}
+
+impl<S: State> Node<S> {
+    fn new(
+        state: S,
+        parent: Option<usize>,
+        action: Option<<S as State>::Action>,
+        depth: usize,
+        rng: &mut StdRng,
+        tracker: &mut Tracker,
+    ) -> Self {
+        let mut untried = if state.end_status().is_some() {
+            vec![]
+        } else {
+            tracker.expand(depth);
+            state.actions_after(action.as_ref())
+        };
+        // Actions are popped from the back when expanding, so shuffle to expand in random order
+        untried.shuffle(rng);
+        Self {
+            state,
+            parent,
+            action,
+            children: vec![],
+            depth,
+            untried,
+            visits: 0,
+            best_reward: f32::MIN,
+        }
+    }
+}
+
+/// Monte Carlo Tree Search for a single-player game.
+/// Since there's no opponent and the game is deterministic, a node is worth the best outcome found
+/// below it, so rewards are backed up with `max` rather than averaged.
+/// Returns stats for every root move, which `MctsResult::best` uses to pick the move leading to
+/// the best outcome found. Once the iterations or the budget run out, the search stops with the
+/// results so far.
+/// The statistics count the states added to the tree, but not the ones played through in rollouts.
+pub fn mcts_move<S: State + Clone>(
+    game_state: &S,
+    config: &MctsConfig,
+    observer: &mut dyn SearchObserver,
+) -> MctsResult<<S as State>::Action> {
+    let max_iterations = config.iterations.unwrap_or(usize::MAX);
+    let mut tracker = Tracker::new(&config.budget, observer);
+    let mut rng = StdRng::seed_from_u64(config.seed);
+
+    let root = Node::new(game_state.clone(), None, None, 0, &mut rng, &mut tracker);
+    let mut nodes = vec![root];
+    // Range of rewards seen so far, used to normalize the exploitation term of UCT
+    let mut min_reward = f32::MAX;
+    let mut max_reward = f32::MIN;
+
+    let mut iteration = 0;
+    while iteration < max_iterations && tracker.out_of_budget().is_none() {
+        iteration += 1;
+
+        // Selection: descend through fully expanded nodes
+        let mut idx = 0;
+        while nodes[idx].untried.is_empty() && !nodes[idx].children.is_empty() {
+            let parent_visits = nodes[idx].visits as f32;
+            idx = *nodes[idx]
+                .children
+                .iter()
+                .max_by(|&&a, &&b| {
+                    let uct = |child: &Node<S>| {
+                        let exploitation = if max_reward > min_reward {
+                            (child.best_reward - min_reward) / (max_reward - min_reward)
+                        } else {
+                            0.
+                        };
+                        exploitation
+                            + config.exploration * (parent_visits.ln() / child.visits as f32).sqrt()
+                    };
+                    uct(&nodes[a]).total_cmp(&uct(&nodes[b]))
+                })
+                .expect("Node was checked to have children");
+        }
+
+        // Expansion: add one untried move as a new child.
+        // Moves back to a state already on the path from the root are dropped instead.
+        while let Some(action) = nodes[idx].untried.pop() {
+            let state = nodes[idx].state.act(&action);
+            tracker.generate();
+            if is_on_path(&nodes, idx, &state) {
+                tracker.prune_duplicate();
+                continue;
+            }
+            let depth = nodes[idx].depth + 1;
+            let child = Node::new(
+                state,
+                Some(idx),
+                Some(action),
+                depth,
+                &mut rng,
+                &mut tracker,
+            );
+            nodes.push(child);
+            let child_idx = nodes.len() - 1;
+            nodes[idx].children.push(child_idx);
+            idx = child_idx;
+            break;
+        }
+
+        // Rollout: play out the game from the new node
+        let reward = rollout(&nodes[idx].state, config, &mut rng);
+        min_reward = min_reward.min(reward);
+        max_reward = max_reward.max(reward);
+
+        // Backup: every node on the path is worth at least this reward
+        let mut current = Some(idx);
+        while let Some(idx) = current {
+            let node = &mut nodes[idx];
+            node.visits += 1;
+            node.best_reward = node.best_reward.max(reward);
+            current = node.parent;
+        }
+    }
+
+    let root_stats = nodes[0]
+        .children
+        .iter()
+        .map(|&idx| ActionStats {
+            action: nodes[idx]
+                .action
+                .clone()
+                .expect("Child nodes always have an action"),
+            visits: nodes[idx].visits,
+            best_reward: nodes[idx].best_reward,
+        })
+        .collect::<Vec<_>>();
+    MctsResult {
+        actions: root_stats,
+        stats: tracker.into_stats(),
+    }
+}
+
+/// Whether `state` is the node at `idx` or one of its ancestors
+fn is_on_path<S: State>(nodes: &[Node<S>], idx: usize, state: &S) -> bool {
+    let mut current = Some(idx);
+    while let Some(idx) = current {
+        if nodes[idx].state == *state {
+            return true;
+        }
+        current = nodes[idx].parent;
+    }
+
+    false
+}
+
+fn rollout<S: State + Clone>(state: &S, config: &MctsConfig, rng: &mut StdRng) -> f32 {
+    let mut state = state.clone();
+    let mut previous = None;
+    // Rollouts never return to a state they've already passed through
+    let mut path = SearchPath::new();
+    path.enter(&state);
+    for _ in 0..config.rollout_depth {
+        if state.end_status().is_some() {
+            break;
+        }
+        let mut next_states = state
+            .actions_after(previous.as_ref())
+            .into_iter()
+            .map(|action| {
+                let next_state = state.act(&action);
+                (action, next_state)
+            })
+            .filter(|(_, next_state)| !path.contains(next_state))
+            .collect::<Vec<_>>();
+        if next_states.is_empty() {
+            break;
+        }
+        let idx = match config.rollout {
+            Rollout::Greedy { epsilon } if rng.gen_range(0..100) >= epsilon => next_states
+                .iter()
+                .enumerate()
+                .max_by(|(_, (_, a)), (_, (_, b))| a.evaluate(false).total_cmp(&b.evaluate(false)))
+                .map(|(idx, _)| idx)
+                .expect("Next states were checked to be non-empty"),
+            _ => rng.gen_range(0..next_states.len()),
+        };
+        let (action, next_state) = next_states.swap_remove(idx);
+        path.enter(&next_state);
+        previous = Some(action);
+        state = next_state;
+    }
+
+    state.evaluate(false)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::game::State;
+    use crate::mcts::{mcts_move, MctsConfig, Rollout};
+    use crate::search::SearchBudget;
+    use crate::testing::{nearly_solved_russian, NumberLine};
+
+    #[test]
+    fn test_visit_counts() {
+        let config = MctsConfig {
+            iterations: Some(500),
+            ..MctsConfig::default()
+        };
+        let result = mcts_move(&NumberLine(0), &config, &mut ());
+
+        assert_eq!(result.actions.len(), 3);
+        // Every iteration expands a new node below the root
+        assert_eq!(result.actions.iter().map(|s| s.visits).sum::<usize>(), 500);
+    }
+
+    #[test]
+    fn test_node_limit() {
+        let config = MctsConfig {
+            iterations: None,
+            budget: SearchBudget {
+                max_nodes: Some(50),
+                ..SearchBudget::default()
+            },
+            ..MctsConfig::default()
+        };
+        let result = mcts_move(&NumberLine(0), &config, &mut ());
+
+        assert!(result.best().is_some());
+        assert_eq!(result.stats.nodes_expanded, 50);
+    }
+
+    #[test]
+    fn test_plays_to_win() {
+        let config = MctsConfig {
+            iterations: Some(200),
+            rollout: Rollout::Greedy { epsilon: 10 },
+            ..MctsConfig::default()
+        };
+        let mut state = nearly_solved_russian();
+        // Wins are all scored the same, so the search may take a detour on the way
+        for _ in 0..20 {
+            if state.is_win() {
+                break;
+            }
+            let result = mcts_move(&state, &config, &mut ());
+            let mv = result.best().expect("Deal isn't finished yet");
+            state = state.act(mv);
+        }
+
+        assert!(state.is_win());
+    }
+
+    #[test]
+    fn test_no_moves() {
+        let result = mcts_move(&NumberLine(10), &MctsConfig::default(), &mut ());
+        assert!(result.best().is_none());
+        assert!(result.actions.is_empty());
+    }
+}
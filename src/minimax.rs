@@ -1,70 +1,255 @@
-use std::time::{Duration, Instant};
+use crate::game::{Reversible, SearchPath, State};
+use crate::search::{Meter, SearchBudget, Tracker};
 
-use crate::game::State;
-
-/// Minimax algorithm for a single-player game
-fn minimax<S: State>(game_state: &S, depth: usize) -> (f32, Vec<<S as State>::Action>) {
+/// Minimax algorithm for a single-player game, continuing a search that reached `game_state`
+/// with `previous`.
+/// Once the budget runs out, the states left to search are scored as if they were at the depth
+/// limit, so the result is the best line found so far.
+/// Moves that lead back to a state in `path` are skipped, since they can't find anything new.
+pub fn minimax_on_path<S: State + Clone, M: Meter>(
+    game_state: &S,
+    previous: Option<&<S as State>::Action>,
+    depth: usize,
+    path: &mut SearchPath<S>,
+    meter: &mut M,
+) -> (f32, Vec<<S as State>::Action>) {
     if depth == 0 || game_state.end_status().is_some() {
-        if let Some(end_state) = game_state.end_status() {
-            if end_state.is_win() {
-                println!("game is won, score: {}", game_state.evaluate(false));
-            }
-        }
+        return (game_state.evaluate(false), vec![]);
+    }
+    if meter.out_of_budget().is_some() {
         return (game_state.evaluate(false), vec![]);
     }
 
-    let legal_moves = game_state.possible_actions();
+    meter.expand(path.len().saturating_sub(1));
+    let legal_moves = game_state.actions_after(previous);
     let mut max_eval = f32::MIN;
     let mut best_path = Vec::new();
 
     for m in legal_moves {
         let simulated_state = game_state.act(&m);
-        let (eval, path) = minimax(&simulated_state, depth - 1);
+        meter.generate();
+        if !path.enter(&simulated_state) {
+            meter.prune_duplicate();
+            continue;
+        }
+        let (eval, line) = minimax_on_path(&simulated_state, Some(&m), depth - 1, path, meter);
+        path.leave(&simulated_state);
         if eval > max_eval {
             max_eval = eval;
             best_path = vec![m];
-            best_path.extend(path);
+            best_path.extend(line);
         }
     }
 
     (max_eval, best_path)
 }
 
-/// Function to choose the best move using minimax algorithm
+/// Same as `minimax_on_path`, but applies and undoes moves on a single state
+pub fn minimax_in_place_on_path<S: Reversible + Clone, M: Meter>(
+    game_state: &mut S,
+    previous: Option<&<S as State>::Action>,
+    depth: usize,
+    path: &mut SearchPath<S>,
+    meter: &mut M,
+) -> (f32, Vec<<S as State>::Action>) {
+    if depth == 0 || game_state.end_status().is_some() {
+        return (game_state.evaluate(false), vec![]);
+    }
+    if meter.out_of_budget().is_some() {
+        return (game_state.evaluate(false), vec![]);
+    }
+
+    meter.expand(path.len().saturating_sub(1));
+    let legal_moves = game_state.actions_after(previous);
+    let mut max_eval = f32::MIN;
+    let mut best_path = Vec::new();
+
+    for m in legal_moves {
+        let undo = game_state.apply_in_place(&m);
+        meter.generate();
+        if path.enter(game_state) {
+            let (eval, line) =
+                minimax_in_place_on_path(game_state, Some(&m), depth - 1, path, meter);
+            path.leave(game_state);
+            if eval > max_eval {
+                max_eval = eval;
+                best_path = vec![m];
+                best_path.extend(line);
+            }
+        } else {
+            meter.prune_duplicate();
+        }
+        game_state.undo(undo);
+    }
+
+    (max_eval, best_path)
+}
+
+/// Function to choose the best move using minimax algorithm.
+/// Searches one depth deeper at a time, and returns the best move so far once the budget runs out.
 pub fn best_move<S>(
     game_state: &S,
     max_depth: Option<usize>,
-    search_time: Option<Duration>,
+    budget: &SearchBudget,
 ) -> Option<(<S as State>::Action, Vec<<S as State>::Action>)>
 where
-    S: State,
+    S: State + Clone,
 {
-    let max_depth = max_depth.or(Some(usize::MAX)).unwrap();
-    let search_time = search_time.or(Some(Duration::MAX)).unwrap();
+    let max_depth = max_depth.unwrap_or(usize::MAX);
 
     let mut best_move = None;
     let mut best_score = f32::MIN;
     let mut best_path = Vec::new();
 
-    let time = Instant::now();
+    let mut path = SearchPath::new();
+    path.enter(game_state);
+
+    let observer = &mut ();
+    let mut tracker = Tracker::new(budget, observer);
+    let legal_moves = game_state.actions_after(None);
+    match legal_moves.len() {
+        0 => return None,
+        1 => return Some((legal_moves[0].clone(), vec![])),
+        _ => {}
+    }
     for depth in 1..=max_depth {
-        let legal_moves = game_state.possible_actions();
-        if legal_moves.len() == 1 {
-            return Some((legal_moves[0].clone(), vec![]));
+        // A pass that found no move to search means every root move leads back to this state,
+        // so searching deeper won't find one either
+        if depth > 1 && (best_move.is_none() || tracker.out_of_budget().is_some()) {
+            break;
         }
-        for m in legal_moves {
+        for m in legal_moves.iter().cloned() {
             let simulated_state = game_state.act(&m);
-            let (score, path) = minimax(&simulated_state, depth - 1);
+            if !path.enter(&simulated_state) {
+                continue;
+            }
+            let (score, line) = minimax_on_path(
+                &simulated_state,
+                Some(&m),
+                depth - 1,
+                &mut path,
+                &mut tracker,
+            );
+            path.leave(&simulated_state);
             if score >= best_score {
                 best_score = score;
                 best_move = Some(m);
-                best_path = path;
+                best_path = line;
             }
-            if time.elapsed() > search_time {
-                return best_move.map(|m| (m, best_path));
+            if tracker.out_of_budget().is_some() {
+                break;
             }
         }
     }
 
     best_move.map(|m| (m, best_path))
 }
+
+/// Same as `best_move`, but searches by applying and undoing moves on `game_state`.
+/// The state is back in its original position when this returns.
+pub fn best_move_in_place<S>(
+    game_state: &mut S,
+    max_depth: Option<usize>,
+    budget: &SearchBudget,
+) -> Option<(<S as State>::Action, Vec<<S as State>::Action>)>
+where
+    S: Reversible + Clone,
+{
+    let max_depth = max_depth.unwrap_or(usize::MAX);
+
+    let mut best_move = None;
+    let mut best_score = f32::MIN;
+    let mut best_path = Vec::new();
+
+    let mut path = SearchPath::new();
+    path.enter(game_state);
+
+    let observer = &mut ();
+    let mut tracker = Tracker::new(budget, observer);
+    let legal_moves = game_state.actions_after(None);
+    match legal_moves.len() {
+        0 => return None,
+        1 => return Some((legal_moves[0].clone(), vec![])),
+        _ => {}
+    }
+    for depth in 1..=max_depth {
+        // A pass that found no move to search means every root move leads back to this state,
+        // so searching deeper won't find one either
+        if depth > 1 && (best_move.is_none() || tracker.out_of_budget().is_some()) {
+            break;
+        }
+        for m in legal_moves.iter().cloned() {
+            let undo = game_state.apply_in_place(&m);
+            if !path.enter(game_state) {
+                game_state.undo(undo);
+                continue;
+            }
+            let (score, line) =
+                minimax_in_place_on_path(game_state, Some(&m), depth - 1, &mut path, &mut tracker);
+            path.leave(game_state);
+            game_state.undo(undo);
+            if score >= best_score {
+                best_score = score;
+                best_move = Some(m);
+                best_path = line;
+            }
+            if tracker.out_of_budget().is_some() {
+                break;
+            }
+        }
+    }
+
+    best_move.map(|m| (m, best_path))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+
+    use crate::minimax::{best_move, best_move_in_place};
+    use crate::search::SearchBudget;
+    use crate::testing::{nearly_solved_russian, NumberLine, Stuck};
+
+    #[test]
+    fn test_best_move() {
+        let (mv, _) = best_move(&NumberLine(0), Some(3), &SearchBudget::default())
+            .expect("Number line has moves");
+        assert_eq!(mv, 3);
+    }
+
+    #[test]
+    fn test_best_move_in_place() {
+        let mut state = nearly_solved_russian();
+        let expected = best_move(&state, Some(2), &SearchBudget::default());
+        let found = best_move_in_place(&mut state, Some(2), &SearchBudget::default());
+        assert_eq!(found, expected);
+        assert_eq!(state, nearly_solved_russian());
+    }
+
+    #[test]
+    fn test_budget_returns_best_so_far() {
+        // Without any limit on depth, only the budget can stop the search
+        let budget = SearchBudget {
+            max_nodes: Some(100),
+            ..SearchBudget::default()
+        };
+        assert!(best_move(&NumberLine(0), None, &budget).is_some());
+
+        let budget = SearchBudget {
+            cancel: Some(Arc::new(AtomicBool::new(true))),
+            ..SearchBudget::default()
+        };
+        assert!(best_move_in_place(&mut nearly_solved_russian(), None, &budget).is_some());
+    }
+
+    #[test]
+    fn test_nothing_to_search() {
+        // Without any limit, these would search deeper forever if they didn't stop at once
+        let budget = SearchBudget::default();
+        for moves in [0, 2] {
+            assert_eq!(best_move(&Stuck(moves), None, &budget), None);
+            assert_eq!(best_move_in_place(&mut Stuck(moves), None, &budget), None);
+        }
+    }
+}
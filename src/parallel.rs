@@ -0,0 +1,440 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::game::{EndState, SearchPath, State};
+use crate::minimax::minimax_on_path;
+use crate::search::{
+    LocalStats, Meter, SearchBudget, SearchObserver, SearchOutcome, SearchResult, StopReason,
+    Tracker,
+};
+
+/// Set of visited states shared between worker threads.
+/// States are split into shards by hash so that workers rarely wait on the same lock.
+struct SharedCache<S> {
+    shards: Vec<Mutex<HashSet<S>>>,
+}
+
+impl<S: Eq + Hash> SharedCache<S> {
+    fn new(shard_count: usize) -> Self {
+        Self {
+            shards: (0..shard_count.max(1))
+                .map(|_| Mutex::new(HashSet::new()))
+                .collect(),
+        }
+    }
+
+    /// Returns `false` if the state was already in the cache
+    fn insert(&self, state: S) -> bool {
+        let mut hasher = DefaultHasher::new();
+        state.hash(&mut hasher);
+        let shard = hasher.finish() as usize % self.shards.len();
+        self.shards[shard]
+            .lock()
+            .expect("No worker should panic while holding the lock")
+            .insert(state)
+    }
+
+    fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| {
+                shard
+                    .lock()
+                    .expect("No worker should panic while holding the lock")
+                    .len()
+            })
+            .sum()
+    }
+}
+
+fn worker_count(threads: Option<usize>) -> usize {
+    threads
+        .or_else(|| thread::available_parallelism().ok().map(|n| n.get()))
+        .unwrap_or(1)
+        .max(1)
+}
+
+/// Depth-first search with the root moves split between `threads` workers
+/// (or one per core if `None`).
+/// Workers share a single visited-state cache, and take the next unsearched root move whenever
+/// they finish one. The first worker to find a win stops all the others.
+/// Like `dfs_r`, the deal is only proven unsolvable if no state was cut off by a limit.
+pub fn parallel_dfs<S: State + Clone>(
+    state: S,
+    max_depth: Option<usize>,
+    budget: &SearchBudget,
+    threads: Option<usize>,
+    observer: &mut dyn SearchObserver,
+) -> SearchResult<<S as State>::Action> {
+    let mut tracker = Tracker::new(budget, observer);
+    if let Some(end_state) = state.end_status() {
+        return tracker.finish(if end_state.is_win() {
+            SearchOutcome::Solved(vec![])
+        } else {
+            SearchOutcome::ProvenUnsolvable { states_explored: 1 }
+        });
+    }
+
+    let max_depth = max_depth.unwrap_or(usize::MAX);
+    let threads = worker_count(threads);
+
+    tracker.expand(0);
+    let root_actions = state.actions_after(None);
+    let tracker = Mutex::new(tracker);
+    let next_root_action = AtomicUsize::new(0);
+    let found = AtomicBool::new(false);
+    let cache = SharedCache::new(threads * 16);
+    cache.insert(state.clone());
+
+    let results = thread::scope(|scope| {
+        let workers = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut stats = LocalStats::new(&tracker);
+                    let mut stopped = None;
+                    let result = loop {
+                        let idx = next_root_action.fetch_add(1, Ordering::Relaxed);
+                        let Some(root_action) = root_actions.get(idx) else {
+                            break Err(stopped);
+                        };
+                        match search_subtree(
+                            &state,
+                            root_action,
+                            &cache,
+                            &found,
+                            &mut stats,
+                            max_depth,
+                        ) {
+                            Ok(path) => {
+                                found.store(true, Ordering::Relaxed);
+                                break Ok(path);
+                            }
+                            Err(Some(reason)) if reason.ends_search() => break Err(Some(reason)),
+                            Err(reason) => stopped = stopped.or(reason),
+                        }
+                        if found.load(Ordering::Relaxed) {
+                            break Err(stopped);
+                        }
+                    };
+                    stats.flush();
+                    result
+                })
+            })
+            .collect::<Vec<_>>();
+
+        workers
+            .into_iter()
+            .map(|worker| worker.join().expect("Search worker panicked"))
+            .collect::<Vec<_>>()
+    });
+
+    let tracker = tracker
+        .into_inner()
+        .expect("No worker should panic while holding the lock");
+    let mut stopped: Option<StopReason> = None;
+    for result in results {
+        match result {
+            Ok(path) => return tracker.finish(SearchOutcome::Solved(path)),
+            // Running out of budget is the more useful reason to give, so it isn't replaced
+            Err(Some(reason)) if stopped.is_none_or(|stopped| !stopped.ends_search()) => {
+                stopped = Some(reason)
+            }
+            Err(_) => (),
+        }
+    }
+    tracker.finish(match stopped {
+        Some(reason) => SearchOutcome::Inconclusive { reason },
+        None => SearchOutcome::ProvenUnsolvable {
+            states_explored: cache.len(),
+        },
+    })
+}
+
+/// Iterative depth-first search of the subtree below one root move.
+/// An explicit stack is used since worker threads have much smaller stacks than the main thread.
+/// Returns the winning line, or why the search of this subtree stopped short (if it did).
+fn search_subtree<S: State + Clone>(
+    root: &S,
+    root_action: &<S as State>::Action,
+    cache: &SharedCache<S>,
+    found: &AtomicBool,
+    stats: &mut LocalStats,
+    max_depth: usize,
+) -> Result<Vec<<S as State>::Action>, Option<StopReason>> {
+    // Each entry is a state on the current path along with its unexplored moves,
+    // reversed so that the "best" moves are popped first
+    let mut stack = Vec::new();
+    let mut path = Vec::new();
+    let mut stopped = None;
+
+    let mut next = Some((root.act(root_action), root_action.clone()));
+    stats.generate();
+    loop {
+        if let Some((state, mv)) = next.take() {
+            if !cache.insert(state.clone()) {
+                stats.prune_duplicate();
+            } else {
+                path.push(mv);
+                match state.end_status() {
+                    Some(EndState::Win) => return Ok(path),
+                    Some(EndState::Loss) => {
+                        path.pop();
+                    }
+                    None if path.len() >= max_depth => {
+                        stopped = Some(StopReason::MaxDepth);
+                        path.pop();
+                    }
+                    None => {
+                        stats.expand(path.len());
+                        let mut actions = state.actions_after(path.last());
+                        actions.reverse();
+                        stack.push((state, actions));
+                    }
+                }
+            }
+        }
+
+        if let Some(reason) = stats.out_of_budget() {
+            return Err(Some(reason));
+        }
+        // Another worker found a win, so it doesn't matter how this search ends
+        if found.load(Ordering::Relaxed) {
+            return Err(None);
+        }
+
+        let Some((state, actions)) = stack.last_mut() else {
+            return Err(stopped);
+        };
+        match actions.pop() {
+            Some(mv) => {
+                next = Some((state.act(&mv), mv));
+                stats.generate();
+            }
+            None => {
+                stack.pop();
+                path.pop();
+            }
+        }
+    }
+}
+
+/// Multithreaded version of `minimax::best_move`.
+/// At each depth, the root moves are split between `threads` workers (or one per core if `None`).
+pub fn parallel_best_move<S: State + Clone>(
+    game_state: &S,
+    max_depth: Option<usize>,
+    budget: &SearchBudget,
+    threads: Option<usize>,
+) -> Option<(<S as State>::Action, Vec<<S as State>::Action>)> {
+    let max_depth = max_depth.unwrap_or(usize::MAX);
+    let threads = worker_count(threads);
+
+    let legal_moves = game_state.actions_after(None);
+    match legal_moves.len() {
+        0 => return None,
+        1 => return Some((legal_moves[0].clone(), vec![])),
+        _ => {}
+    }
+
+    // Best (score, root move index, path) found so far. Ties go to the later move, like `best_move`
+    let best = Mutex::new(None::<(f32, usize, Vec<<S as State>::Action>)>);
+    let observer = &mut ();
+    let tracker = Mutex::new(Tracker::new(budget, observer));
+    for depth in 1..=max_depth {
+        // Like `best_move`, a pass with no move to search means deeper passes won't have one either
+        let stopped = tracker
+            .lock()
+            .expect("No worker should panic")
+            .out_of_budget();
+        let searched = best.lock().expect("No worker should panic").is_some();
+        if depth > 1 && (!searched || stopped.is_some()) {
+            break;
+        }
+        let next_move = AtomicUsize::new(0);
+        thread::scope(|scope| {
+            for _ in 0..threads {
+                scope.spawn(|| {
+                    let mut stats = LocalStats::new(&tracker);
+                    // Every worker searches at least one move, so that there's a move to return
+                    // even if the budget ran out before the search started
+                    loop {
+                        let idx = next_move.fetch_add(1, Ordering::Relaxed);
+                        let Some(m) = legal_moves.get(idx) else {
+                            break;
+                        };
+                        let simulated_state = game_state.act(m);
+                        let mut path = SearchPath::new();
+                        path.enter(game_state);
+                        if !path.enter(&simulated_state) {
+                            continue;
+                        }
+                        let (score, line) = minimax_on_path(
+                            &simulated_state,
+                            Some(m),
+                            depth - 1,
+                            &mut path,
+                            &mut stats,
+                        );
+                        let mut best = best.lock().expect("No worker should panic");
+                        if best.as_ref().is_none_or(|(best_score, best_idx, _)| {
+                            score > *best_score || (score == *best_score && idx > *best_idx)
+                        }) {
+                            *best = Some((score, idx, line));
+                        }
+                        if stats.out_of_budget().is_some() {
+                            break;
+                        }
+                    }
+                    stats.flush();
+                });
+            }
+        });
+    }
+
+    let (_, idx, path) = best.into_inner().expect("No worker should panic")?;
+    Some((legal_moves[idx].clone(), path))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+
+    use crate::game::State;
+    use crate::parallel::{parallel_best_move, parallel_dfs, SharedCache};
+    use crate::search::{SearchBudget, SearchOutcome, StopReason};
+    use crate::testing::{nearly_solved_russian, DeadEnd, NumberLine, Stuck};
+
+    #[test]
+    fn test_shared_cache() {
+        let cache = SharedCache::new(4);
+        assert!(cache.insert(NumberLine(1)));
+        assert!(cache.insert(NumberLine(2)));
+        assert!(!cache.insert(NumberLine(1)));
+    }
+
+    #[test]
+    fn test_parallel_dfs() {
+        for threads in [1, 4] {
+            let path = parallel_dfs(
+                NumberLine(0),
+                Some(20),
+                &SearchBudget::default(),
+                Some(threads),
+                &mut (),
+            )
+            .outcome
+            .into_path()
+            .expect("Number line should be solvable");
+            assert_eq!(path.iter().sum::<i32>(), 10);
+        }
+    }
+
+    #[test]
+    fn test_parallel_dfs_russian() {
+        let mut state = nearly_solved_russian();
+        let path = parallel_dfs(
+            state.clone(),
+            None,
+            &SearchBudget::default(),
+            Some(4),
+            &mut (),
+        )
+        .outcome
+        .into_path()
+        .expect("Deal should be solvable");
+
+        for mv in &path {
+            state = state.act(mv);
+        }
+        assert!(state.is_win());
+    }
+
+    #[test]
+    fn test_parallel_dfs_depth_limit() {
+        // The shortest win takes 4 moves
+        assert_eq!(
+            parallel_dfs(
+                NumberLine(0),
+                Some(3),
+                &SearchBudget::default(),
+                Some(2),
+                &mut ()
+            )
+            .outcome,
+            SearchOutcome::Inconclusive {
+                reason: StopReason::MaxDepth
+            }
+        );
+        // A limit of 4 moves is enough, the same as for `dfs_r`
+        let path = parallel_dfs(
+            NumberLine(0),
+            Some(4),
+            &SearchBudget::default(),
+            Some(2),
+            &mut (),
+        )
+        .outcome
+        .into_path()
+        .expect("The shortest win fits within the limit");
+        assert_eq!(path.len(), 4);
+    }
+
+    #[test]
+    fn test_parallel_dfs_unsolvable() {
+        for threads in [1, 4] {
+            assert_eq!(
+                parallel_dfs(
+                    DeadEnd(0),
+                    None,
+                    &SearchBudget::default(),
+                    Some(threads),
+                    &mut ()
+                )
+                .outcome,
+                SearchOutcome::ProvenUnsolvable { states_explored: 7 }
+            );
+        }
+    }
+
+    #[test]
+    fn test_parallel_best_move() {
+        let (mv, _) =
+            parallel_best_move(&NumberLine(0), Some(3), &SearchBudget::default(), Some(3))
+                .expect("Number line has moves");
+        assert_eq!(mv, 3);
+
+        // Without any limit, it would search deeper forever if it didn't stop at once
+        for moves in [0, 2] {
+            let found = parallel_best_move(&Stuck(moves), None, &SearchBudget::default(), Some(2));
+            assert_eq!(found, None);
+        }
+    }
+
+    #[test]
+    fn test_parallel_budget() {
+        let budget = SearchBudget {
+            cancel: Some(Arc::new(AtomicBool::new(true))),
+            ..SearchBudget::default()
+        };
+        assert_eq!(
+            parallel_dfs(NumberLine(0), None, &budget, Some(4), &mut ()).outcome,
+            SearchOutcome::Inconclusive {
+                reason: StopReason::Cancelled
+            }
+        );
+
+        assert!(parallel_best_move(&NumberLine(0), None, &budget, Some(2)).is_some());
+
+        // Without a depth limit, only the budget stops the search
+        let budget = SearchBudget {
+            max_nodes: Some(1000),
+            ..SearchBudget::default()
+        };
+        assert!(parallel_best_move(&NumberLine(0), None, &budget, Some(2)).is_some());
+    }
+}
@@ -1,13 +1,16 @@
 use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet};
 use std::fmt::{Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
 
 use derivative::Derivative;
-use rand::prelude::SliceRandom;
-use rand::thread_rng;
 use serde::{Deserialize, Serialize};
 
-use crate::game::{Card, EndState, MaybeCard, Rank, State};
+use crate::game::{
+    canonical_order, heap_size_of, parse_grid_row, seeded_shuffle, words, Card, Deal, DealError,
+    EndState, History, MaybeCard, MoveError, ParseError, Rank, Reversible, State,
+};
 
 // Rules:
 // - Number cards are stacked by alternating colour and decreasing value,
@@ -18,105 +21,77 @@ use crate::game::{Card, EndState, MaybeCard, Rank, State};
 //   completed stacks of face cards.
 // - The free cell can store a single card of any type.
 
-// TODO: custom implementation of Hash that doesn't include history
-#[derive(Derivative, Clone, Eq, Serialize, Deserialize)]
-#[derivative(Debug, Hash, PartialEq)]
+// The positions of the columns only matter for display, so `Hash` and `Eq` treat the tableau as
+// a multiset of columns. The tableau itself is never reordered, so moves always refer to the real
+// column indices.
+
+/// # JSON
+/// An object with these fields, where columns are indices from 0 and list their cards from the
+/// bottom up:
+/// - `free_cell`: a card, or `null`
+/// - `tableau`: 9 lists of cards
+/// - `history`: the moves played so far, oldest first. Optional, and empty if left out.
+///
+/// Cards are written like `game::Card`. Moves are one of `{"Stack": {"cards", "from", "to"}}`,
+/// where `cards` is the run being moved from the bottom up, `{"Free": {"card", "from"}}` or
+/// `{"Unfree": {"card", "to"}}`.
+///
+/// Games are checked with `Deal::validate` as they're read.
+#[derive(Derivative, Clone, Serialize, Deserialize)]
+#[derivative(Debug)]
+#[serde(try_from = "UncheckedRussian")]
 pub struct ProletariatsPatience {
     free_cell: Option<Card>,
     tableau: [Vec<Card>; 9],
-    #[derivative(PartialEq = "ignore")]
-    #[derivative(Hash = "ignore")]
     #[derivative(Debug = "ignore")]
-    history: Vec<Move>,
+    #[serde(default)]
+    history: History<Move>,
+}
+
+/// A game as read from JSON, before it's been validated
+#[derive(Deserialize)]
+struct UncheckedRussian {
+    free_cell: Option<Card>,
+    tableau: [Vec<Card>; 9],
+    #[serde(default)]
+    history: History<Move>,
+}
+
+impl TryFrom<UncheckedRussian> for ProletariatsPatience {
+    type Error = DealError;
+
+    fn try_from(unchecked: UncheckedRussian) -> Result<Self, DealError> {
+        let state = Self {
+            free_cell: unchecked.free_cell,
+            tableau: unchecked.tableau,
+            history: unchecked.history,
+        };
+        state.validate()?;
+        Ok(state)
+    }
 }
 
 impl ProletariatsPatience {
     pub fn new(tableau: [Vec<Card>; 9]) -> Self {
         Self {
+            free_cell: None,
             tableau,
-            ..Self::default()
+            history: History::new(),
         }
     }
 
-    pub fn default() -> Self {
-        Self {
-            free_cell: None,
-            tableau: Self::random_tableau(),
-            history: Vec::new(),
-        }
+    /// Deals the cards in the order given by `seed`, so a seed always gives the same deal
+    pub fn deal(seed: u64) -> Self {
+        Self::new(Self::shuffled_tableau(seed))
     }
 
     pub fn apply_move(&self, mv: Move) -> Self {
-        let mut history = self.history.clone();
-        history.push(mv.clone());
-
-        let mut tableau = self.tableau.clone();
-        match mv {
-            Move::Free { card, from } => {
-                // if card.suit == Spade && card.rank == Ace {
-                //     println!("Freeing Ace {mv:?}");
-                // }
-
-                let top_card = tableau[from].pop().unwrap();
-                // TODO: We shouldn't need to validate these here; rely on tests and trust the code
-                if card != top_card {
-                    println!("{mv:?}");
-                    panic!("Tried to free card that didn't match top card of given column");
-                }
-                if self.free_cell.is_some() {
-                    println!("{self:?}");
-                    println!("{mv:?}");
-                    panic!("Tried to place a card in free cell but it was not empty");
-                }
-                Self {
-                    free_cell: Some(card),
-                    tableau,
-                    history,
-                }
-            }
-            Move::Unfree { card, to } => {
-                // if card.suit == Spade && card.rank == Ace {
-                //     println!("Unfreeing Ace {mv:?}");
-                // }
-
-                if self.free_cell.is_none() {
-                    panic!("Tried to remove a card from free cell but there wasn't one");
-                }
-                tableau[to].push(card);
-                Self {
-                    free_cell: None,
-                    tableau,
-                    history,
-                }
-            }
-            Move::Stack {
-                ref cards,
-                from,
-                to,
-            } => {
-                // for card in cards {
-                //     if card.suit == Spade && card.rank == Ace {
-                //         println!("Moving Ace {:?}", &mv);
-                //     }
-                // }
-
-                let moved_cards = tableau[from]
-                    .drain((tableau[from].len() - cards.len())..)
-                    .collect::<Vec<_>>();
-                if &moved_cards != cards {
-                    panic!("Moved cards don't match given cards {moved_cards:?} != {cards:?}");
-                }
-                tableau[to].extend(cards);
-                Self {
-                    free_cell: self.free_cell,
-                    tableau,
-                    history,
-                }
-            }
-        }
+        let mut state = self.clone();
+        state.apply_in_place(&mv);
+        state
     }
 
-    fn random_tableau() -> [Vec<Card>; 9] {
+    fn shuffled_tableau(seed: u64) -> [Vec<Card>; 9] {
         let mut cards = Card::default_deck()
             .into_iter()
             .filter(|card| {
@@ -127,7 +102,7 @@ impl ProletariatsPatience {
             })
             .collect::<Vec<_>>();
 
-        cards.shuffle(&mut thread_rng());
+        seeded_shuffle(&mut cards, seed);
 
         let chunk_size = cards.len() / 9;
         [
@@ -143,36 +118,38 @@ impl ProletariatsPatience {
         ]
     }
 
-    /// Panics if the tableau isn't valid.
+    /// Checks that the tableau holds each card of the deck exactly once.
     /// Meant to be used after manually constructing a deck to ensure no mistakes were made.
-    pub fn validate_tableau(&self) {
+    pub fn validate_tableau(&self) -> Result<(), DealError> {
         let mut seen = HashSet::new();
-        if let Some(free_card) = &self.free_cell {
-            seen.insert(free_card);
-        }
-        for column in &self.tableau {
-            for card in column {
-                // This deck doesn't contain 2, 3, 4, or 5
-                if card.rank <= Rank::Five && card.rank >= Rank::Two {
-                    panic!("Found card with invalid rank: {card:?}");
-                }
-                // We don't want any duplicates
-                if !seen.insert(card) {
-                    panic!("Found duplicate card: {card:?}");
-                }
+        let cards = self.free_cell.iter().chain(self.tableau.iter().flatten());
+        for card in cards {
+            let name = || card.to_string().trim().to_owned();
+            // This deck doesn't contain 2, 3, 4, or 5
+            if card.rank <= Rank::Five && card.rank >= Rank::Two {
+                return Err(DealError::ExcludedRank { card: name() });
+            }
+            // We don't want any duplicates
+            if !seen.insert(card) {
+                return Err(DealError::Duplicate { card: name() });
             }
         }
 
         // There should be exactly 36 cards
         if seen.len() != 36 {
-            panic!("Found invalid amount of cards: {}", seen.len());
+            return Err(DealError::WrongCardCount {
+                expected: 36,
+                found: seen.len(),
+            });
         }
+        Ok(())
     }
 
     pub fn legal_moves(&self) -> Vec<Move> {
-        self.validate_tableau(); // TODO: remove
-
         let mut moves = Vec::new();
+        // Columns are visited in canonical order so that permutations of the same tableau generate
+        // the same moves in the same order (up to the column indices)
+        let column_order = canonical_order(&self.tableau);
 
         // Legal moves:
         // - We can move all top cards to the free cell, given that:
@@ -185,7 +162,8 @@ impl ProletariatsPatience {
         //     - Determine the stackable depth, that is the number of cards that form a valid stack
         //     - Then, starting at the bottom of the stack (i.e. the deepest card):
         //       - Check for valid destinations (e.g. top card is stackable or empty column)
-        for (from_idx, from_col) in self.tableau.iter().enumerate() {
+        for &from_idx in &column_order {
+            let from_col = &self.tableau[from_idx];
             // No moves can be made from this column (completed face stacks are locked)
             if from_col.is_empty() || Self::is_completed_face_stack(from_col) {
                 continue;
@@ -221,7 +199,8 @@ impl ProletariatsPatience {
             while !stack.is_empty() {
                 let first_card = stack.last().expect("Vec was checked to be non-empty");
                 let mut moved_to_empty_column = false; // all empty column moves are the same
-                for (to_idx, to_col) in self.tableau.iter().enumerate() {
+                for &to_idx in &column_order {
+                    let to_col = &self.tableau[to_idx];
                     if from_idx == to_idx {
                         continue;
                     }
@@ -256,12 +235,21 @@ impl ProletariatsPatience {
 
         // Generate moves for card in free cell
         if let Some(free_card) = self.free_cell {
-            for (to_idx, to_col) in self.tableau.iter().enumerate() {
+            let mut unfreed_to_empty_column = false; // all empty column moves are the same
+            for &to_idx in &column_order {
+                let to_col = &self.tableau[to_idx];
                 // This is a valid destination if the free card can stack on the top card,
                 // or if the column is empty
-                if to_col.is_empty()
-                    || free_card
-                        .can_stack_on(to_col.last().expect("Vec was checked to be non-empty"))
+                if to_col.is_empty() {
+                    if !unfreed_to_empty_column {
+                        moves.push(Move::Unfree {
+                            card: free_card,
+                            to: to_idx,
+                        });
+                        unfreed_to_empty_column = true;
+                    }
+                } else if free_card
+                    .can_stack_on(to_col.last().expect("Vec was checked to be non-empty"))
                 {
                     moves.push(Move::Unfree {
                         card: free_card,
@@ -271,55 +259,100 @@ impl ProletariatsPatience {
             }
         }
 
-        // Remove all moves we used in the last `n` turns to prevent cycles
-        // TODO: There's probably a better way to handle cycles than this
-        moves.retain(|m| {
-            !self
-                .history
-                .iter()
-                .rev()
-                .take(8)
-                .collect::<Vec<_>>()
-                .contains(&m)
-        });
+        // Sort descending so the "best" moves are at the front
+        moves.sort_unstable();
+        moves.reverse();
 
-        if !self.history.is_empty() {
-            let last_move = self
-                .history
-                .last()
-                .expect("History was checked to be non-empty");
+        moves
+    }
 
-            // Remove moves that are just moving the same cards as the last move
-            let last_cards = match last_move {
-                Move::Free { card, .. } | Move::Unfree { card, .. } => &vec![*card],
-                Move::Stack { cards, .. } => cards,
-            };
-            moves.retain(|m| {
-                let cards = match m {
-                    Move::Free { card, .. } | Move::Unfree { card, .. } => &vec![*card],
-                    Move::Stack { cards, .. } => cards,
-                };
-                cards != last_cards
-            });
+    /// Checks that a move follows the rules, so that applying it can trust it
+    pub fn check_move(&self, mv: &Move) -> Result<(), MoveError> {
+        if self.end_status().is_some() {
+            return Err(MoveError::GameOver);
+        }
+        let check_column = |column: usize| {
+            if column < self.tableau.len() {
+                Ok(())
+            } else {
+                Err(MoveError::NoSuchColumn { column })
+            }
+        };
+        // Completed face card stacks are locked, like in `legal_moves`
+        let check_source = |column: usize| {
+            check_column(column)?;
+            if Self::is_completed_face_stack(&self.tableau[column]) {
+                Err(MoveError::CompletedStack { column })
+            } else {
+                Ok(())
+            }
+        };
 
-            // If we just freed a card, we can't unfree it immediately
-            if last_move.is_free() {
-                moves.retain(|m| !m.is_unfree());
+        match mv {
+            &Move::Free { card, from } => {
+                check_source(from)?;
+                let top_card = self.tableau[from]
+                    .last()
+                    .ok_or(MoveError::EmptyColumn { column: from })?;
+                if *top_card != card {
+                    return Err(MoveError::NotOnTop {
+                        cards: card.to_string().trim().to_owned(),
+                        column: from,
+                    });
+                }
+                if self.free_cell.is_some() {
+                    return Err(MoveError::FreeCellOccupied);
+                }
+            }
+            &Move::Unfree { card, to } => {
+                check_column(to)?;
+                match self.free_cell {
+                    None => return Err(MoveError::FreeCellEmpty),
+                    Some(free_card) if free_card != card => {
+                        return Err(MoveError::NotInFreeCell {
+                            card: card.to_string().trim().to_owned(),
+                        })
+                    }
+                    Some(_) => (),
+                }
+                self.check_destination(&[card], to)?;
+            }
+            Move::Stack { cards, from, to } => {
+                check_source(*from)?;
+                check_column(*to)?;
+                if from == to {
+                    return Err(MoveError::SameColumn { column: *from });
+                }
+                if cards.is_empty() || !self.tableau[*from].ends_with(cards) {
+                    return Err(MoveError::NotOnTop {
+                        cards: display_cards(cards),
+                        column: *from,
+                    });
+                }
+                if !cards
+                    .windows(2)
+                    .all(|pair| Self::can_stack(&pair[1], &pair[0]))
+                {
+                    return Err(MoveError::NotARun {
+                        cards: display_cards(cards),
+                    });
+                }
+                self.check_destination(cards, *to)?;
             }
         }
 
-        // TODO: similarly we can remove moves that are reversions of the previous move
-        // TODO: Should we remove _all_ moves that could just be undone next turn?
+        Ok(())
+    }
 
-        if moves.len() > 148 {
-            println!("move len: {}", moves.len());
+    /// Cards can go on an empty column, or on a card the bottom moved card can stack on
+    fn check_destination(&self, cards: &[Card], to: usize) -> Result<(), MoveError> {
+        match self.tableau[to].last() {
+            Some(top_card) if !cards[0].can_stack_on(top_card) => Err(MoveError::CannotStack {
+                cards: display_cards(cards),
+                column: to,
+            }),
+            _ => Ok(()),
         }
-
-        // Sort descending so the "best" moves are at the front
-        moves.sort_unstable();
-        moves.reverse();
-
-        moves
     }
 
     fn can_stack(top: &Card, bottom: &Card) -> bool {
@@ -383,20 +416,24 @@ impl ProletariatsPatience {
         self.is_win() || self.legal_moves().is_empty()
     }
 
+    #[allow(dead_code)]
     pub fn display_history(&self) {
-        for mv in &self.history {
+        for mv in self.history.to_vec() {
             println!("{mv}");
         }
     }
 
+    #[allow(dead_code)]
     pub fn tableau(&self) -> &[Vec<Card>; 9] {
         &self.tableau
     }
 
-    pub fn history(&self) -> &Vec<Move> {
+    #[allow(dead_code)]
+    pub fn history(&self) -> &History<Move> {
         &self.history
     }
 
+    #[allow(dead_code)]
     pub fn peek_column(&self, column: usize) -> Option<&Card> {
         self.tableau[column].last()
     }
@@ -529,7 +566,7 @@ impl ProletariatsPatience {
         // Penalize repetitive moves
         if weight_repetition_penalty > 0.0 {
             let mut move_counts = HashMap::new();
-            for mv in &self.history {
+            for mv in self.history.recent() {
                 *move_counts.entry(mv).or_insert(0) += 1;
             }
 
@@ -574,18 +611,37 @@ pub enum Move {
 }
 
 impl Move {
-    pub fn is_free(&self) -> bool {
-        match *self {
-            Move::Free { .. } => true,
+    /// Whether this move puts the cards moved by `previous` straight back where they came from
+    pub fn undoes(&self, previous: &Move) -> bool {
+        match (previous, self) {
+            (Move::Free { card, from }, Move::Unfree { card: back, to })
+            | (
+                Move::Unfree { card, to: from },
+                Move::Free {
+                    card: back,
+                    from: to,
+                },
+            ) => card == back && from == to,
+            (
+                Move::Stack { cards, from, to },
+                Move::Stack {
+                    cards: back,
+                    from: back_from,
+                    to: back_to,
+                },
+            ) => cards == back && from == back_to && to == back_from,
             _ => false,
         }
     }
 
+    #[allow(dead_code)]
+    pub fn is_free(&self) -> bool {
+        matches!(self, Move::Free { .. })
+    }
+
+    #[allow(dead_code)]
     pub fn is_unfree(&self) -> bool {
-        match *self {
-            Move::Unfree { .. } => true,
-            _ => false,
-        }
+        matches!(self, Move::Unfree { .. })
     }
 }
 
@@ -618,26 +674,53 @@ impl Ord for Move {
     }
 }
 
+fn display_cards(cards: &[Card]) -> String {
+    cards
+        .iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 impl Display for Move {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Move::Free { card, from } => write!(f, "{from}->\u{1F0A0}\t{card}"),
             Move::Unfree { card, to } => write!(f, "\u{1F0A0}->{to}\t{card}"),
             Move::Stack { cards, from, to } => {
-                write!(
-                    f,
-                    "{from}->{to}\t{}",
-                    cards
-                        .iter()
-                        .map(|c| c.to_string())
-                        .collect::<Vec<_>>()
-                        .join(" ")
-                )
+                write!(f, "{from}->{to}\t{}", display_cards(cards))
             }
         }
     }
 }
 
+impl PartialEq for ProletariatsPatience {
+    fn eq(&self, other: &Self) -> bool {
+        self.free_cell == other.free_cell
+            && canonical_order(&self.tableau)
+                .iter()
+                .zip(canonical_order(&other.tableau))
+                .all(|(&a, b)| self.tableau[a] == other.tableau[b])
+    }
+}
+
+impl Eq for ProletariatsPatience {}
+
+impl Hash for ProletariatsPatience {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.free_cell.hash(state);
+        for idx in canonical_order(&self.tableau) {
+            self.tableau[idx].hash(state);
+        }
+    }
+}
+
+impl Deal for ProletariatsPatience {
+    fn validate(&self) -> Result<(), DealError> {
+        self.validate_tableau()
+    }
+}
+
 impl State for ProletariatsPatience {
     type Action = Move;
 
@@ -661,6 +744,25 @@ impl State for ProletariatsPatience {
         self.apply_move(action.to_owned())
     }
 
+    fn try_act(&self, action: &Move) -> Result<Self, MoveError> {
+        self.check_move(action)?;
+        Ok(self.act(action))
+    }
+
+    /// Nothing moves by itself in this game, so undoing the previous move always leads straight
+    /// back to the state before it
+    fn is_redundant(&self, previous: &Move, action: &Move) -> bool {
+        action.undoes(previous)
+    }
+
+    fn last_action(&self) -> Option<&Move> {
+        self.history.last()
+    }
+
+    fn heap_size(&self) -> usize {
+        heap_size_of(&self.tableau)
+    }
+
     fn evaluate(&self, print_components: bool) -> f32 {
         match self.end_status() {
             Some(end_state) => match end_state {
@@ -670,6 +772,92 @@ impl State for ProletariatsPatience {
             None => self.heuristic_score(print_components),
         }
     }
+
+    /// Counts the places where a column is out of order, plus a card in the free cell.
+    /// A column is out of order at every card that can't stack on the card below it, and at its
+    /// bottom card if that card can't start a completed stack (i.e. a number card other than 10).
+    /// A move only takes cards from the top of one column, so it can fix at most one of these.
+    fn cost_to_go(&self) -> usize {
+        let mut cost = self.free_cell.iter().count();
+        for column in &self.tableau {
+            if column
+                .first()
+                .is_some_and(|card| !card.is_face_or_ace() && card.rank != Rank::Ten)
+            {
+                cost += 1;
+            }
+            cost += column
+                .windows(2)
+                .filter(|cards| !Self::can_stack(&cards[1], &cards[0]))
+                .count();
+        }
+
+        cost
+    }
+}
+
+impl Reversible for ProletariatsPatience {
+    /// Every move can be undone by moving the same cards back
+    type Undo = Move;
+
+    fn apply_in_place(&mut self, mv: &Move) -> Move {
+        match *mv {
+            // Moves are checked by `check_move` (or generated legal), so they're trusted here
+            Move::Free { card, from } => {
+                // if card.suit == Spade && card.rank == Ace {
+                //     println!("Freeing Ace {mv:?}");
+                // }
+
+                self.tableau[from].pop();
+                self.free_cell = Some(card);
+            }
+            Move::Unfree { card, to } => {
+                // if card.suit == Spade && card.rank == Ace {
+                //     println!("Unfreeing Ace {mv:?}");
+                // }
+
+                self.free_cell = None;
+                self.tableau[to].push(card);
+            }
+            Move::Stack {
+                ref cards,
+                from,
+                to,
+            } => {
+                // for card in cards {
+                //     if card.suit == Spade && card.rank == Ace {
+                //         println!("Moving Ace {:?}", &mv);
+                //     }
+                // }
+
+                let split_idx = self.tableau[from].len() - cards.len();
+                let moved_cards = self.tableau[from].split_off(split_idx);
+                self.tableau[to].extend(moved_cards);
+            }
+        }
+        self.history = self.history.with(mv.clone());
+
+        mv.clone()
+    }
+
+    fn undo(&mut self, mv: Move) {
+        match mv {
+            Move::Free { card, from } => {
+                self.free_cell = None;
+                self.tableau[from].push(card);
+            }
+            Move::Unfree { card, to } => {
+                self.tableau[to].pop();
+                self.free_cell = Some(card);
+            }
+            Move::Stack { cards, from, to } => {
+                let split_idx = self.tableau[to].len() - cards.len();
+                self.tableau[to].truncate(split_idx);
+                self.tableau[from].extend(cards);
+            }
+        }
+        self.history.pop();
+    }
 }
 
 impl Display for ProletariatsPatience {
@@ -710,11 +898,71 @@ impl Display for ProletariatsPatience {
     }
 }
 
+impl FromStr for ProletariatsPatience {
+    type Err = ParseError;
+
+    /// Reads the grid printed by `Display`: one row of cards per line, each column 4 characters
+    /// wide, with the free cell after a tab on the first line.
+    /// Cards can be left or right aligned in their column, and suits can be letters (`10H`)
+    /// or symbols (`10♥`).
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut tableau: [Vec<Card>; 9] = Default::default();
+        let mut free_cell = None;
+
+        let lines = input
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty());
+        for (row, (line_idx, line)) in lines.enumerate() {
+            let line_no = line_idx + 1;
+            let (grid, free) = line.split_once('\t').unwrap_or((line, ""));
+
+            let grid_width = grid.chars().count();
+            for (column, word) in words(free).map(|(column, word)| (column + grid_width + 1, word))
+            {
+                if word == "\u{1F0A0}" {
+                    continue;
+                }
+                if row > 0 {
+                    return Err(ParseError::at(
+                        line_no,
+                        column,
+                        "the free cell is only on the first line",
+                    ));
+                }
+                if free_cell.is_some() {
+                    return Err(ParseError::at(
+                        line_no,
+                        column,
+                        "the free cell only holds one card",
+                    ));
+                }
+                free_cell = Some(
+                    word.parse()
+                        .map_err(|message| ParseError::at(line_no, column, message))?,
+                );
+            }
+
+            parse_grid_row(&mut tableau, row, line_no, grid)?;
+        }
+
+        let state = Self {
+            free_cell,
+            ..Self::new(tableau)
+        };
+        state.validate_tableau()?;
+        Ok(state)
+    }
+}
+
 #[cfg(test)]
 mod test {
+    use crate::astar::astar;
     use crate::game::Rank::*;
     use crate::game::Suit::*;
-    use crate::states::russian;
+    use crate::search::SearchBudget;
+    use crate::states::{russian, RUSSIAN_PRESETS};
+    use crate::testing::nearly_solved_russian;
 
     use super::*;
 
@@ -832,4 +1080,344 @@ mod test {
             "Additional moves: {additional_moves:?}"
         )
     }
+
+    #[test]
+    fn test_undo() {
+        let state = russian();
+        let mut in_place = state.clone();
+        for mv in state.legal_moves() {
+            let undo = in_place.apply_in_place(&mv);
+            assert_eq!(in_place, state.apply_move(mv.clone()));
+            assert_eq!(in_place.history().last(), Some(&mv));
+
+            in_place.undo(undo);
+            assert_eq!(in_place, state);
+            assert!(in_place.history().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_try_act() {
+        let state = russian();
+        for mv in state.legal_moves() {
+            assert_eq!(state.try_act(&mv), Ok(state.act(&mv)));
+        }
+
+        let card = *state.tableau[0].last().expect("Deal has no empty columns");
+        let other_card = *state.tableau[1].last().expect("Deal has no empty columns");
+        assert_eq!(
+            state.try_act(&Move::Free {
+                card: other_card,
+                from: 0
+            }),
+            Err(MoveError::NotOnTop {
+                cards: other_card.to_string().trim().to_owned(),
+                column: 0
+            })
+        );
+        assert_eq!(
+            state.try_act(&Move::Unfree { card, to: 1 }),
+            Err(MoveError::FreeCellEmpty)
+        );
+        assert_eq!(
+            state.try_act(&Move::Stack {
+                cards: vec![card],
+                from: 0,
+                to: 9
+            }),
+            Err(MoveError::NoSuchColumn { column: 9 })
+        );
+        assert_eq!(
+            state.try_act(&Move::Stack {
+                cards: vec![card],
+                from: 0,
+                to: 0
+            }),
+            Err(MoveError::SameColumn { column: 0 })
+        );
+
+        let freed = state.act(&Move::Free { card, from: 0 });
+        assert_eq!(
+            freed.try_act(&Move::Free {
+                card: other_card,
+                from: 1
+            }),
+            Err(MoveError::FreeCellOccupied)
+        );
+    }
+
+    #[test]
+    fn test_completed_stack_locked() {
+        let state = nearly_solved_russian();
+        let jack = Card {
+            rank: Jack,
+            suit: Heart,
+        };
+        let queen = Card {
+            rank: Queen,
+            suit: Heart,
+        };
+        assert_eq!(
+            state.try_act(&Move::Free {
+                card: jack,
+                from: 4
+            }),
+            Err(MoveError::CompletedStack { column: 4 })
+        );
+        assert_eq!(
+            state.try_act(&Move::Stack {
+                cards: vec![queen, jack],
+                from: 4,
+                to: 8
+            }),
+            Err(MoveError::CompletedStack { column: 4 })
+        );
+        assert!(!state
+            .legal_moves()
+            .iter()
+            .any(|mv| matches!(mv, Move::Free { from: 4, .. } | Move::Stack { from: 4, .. })));
+    }
+
+    #[test]
+    fn test_moves_ignore_history() {
+        let state = ProletariatsPatience::deal(0);
+        // A move that can be undone straight away
+        let (mv, moved) = state
+            .legal_moves()
+            .into_iter()
+            .map(|mv| {
+                let moved = state.act(&mv);
+                (mv, moved)
+            })
+            .find(|(mv, moved)| moved.legal_moves().iter().any(|m| m.undoes(mv)))
+            .expect("Deal should have a move that can be undone");
+
+        let mut fresh = moved.clone();
+        fresh.history = History::new();
+        assert_eq!(moved.legal_moves(), fresh.legal_moves());
+
+        // Searches skip undoing the last move, but only when they know what it was
+        let undoes = |m: &Move| m.undoes(&mv);
+        assert!(!moved.actions_after(None).iter().any(undoes));
+        assert!(fresh.actions_after(None).iter().any(undoes));
+        assert_eq!(
+            moved.actions_after(None).len() + 1,
+            moved.legal_moves().len()
+        );
+    }
+
+    /// Moves the column at index `i` to index `(i + 5) % 9`
+    fn rotate(state: &ProletariatsPatience) -> ProletariatsPatience {
+        let mut rotated = state.clone();
+        rotated.tableau.rotate_right(5);
+        rotated
+    }
+
+    fn rotate_move(mv: Move) -> Move {
+        let rotate_idx = |idx: usize| (idx + 5) % 9;
+        match mv {
+            Move::Stack { cards, from, to } => Move::Stack {
+                cards,
+                from: rotate_idx(from),
+                to: rotate_idx(to),
+            },
+            Move::Free { card, from } => Move::Free {
+                card,
+                from: rotate_idx(from),
+            },
+            Move::Unfree { card, to } => Move::Unfree {
+                card,
+                to: rotate_idx(to),
+            },
+        }
+    }
+
+    #[test]
+    fn test_column_permutation() {
+        for state in [russian(), nearly_solved_russian()] {
+            let rotated = rotate(&state);
+            assert_ne!(state.tableau, rotated.tableau);
+            assert_eq!(state, rotated);
+
+            let mut cache = HashSet::new();
+            cache.insert(state.clone());
+            assert!(!cache.insert(rotated.clone()));
+
+            let moves = state
+                .legal_moves()
+                .into_iter()
+                .map(rotate_move)
+                .collect::<Vec<_>>();
+            assert_eq!(moves, rotated.legal_moves());
+        }
+    }
+
+    #[test]
+    fn test_column_permutation_solution() {
+        // The path is found on the rotated deal, so it has to use the rotated indices
+        let mut state = rotate(&nearly_solved_russian());
+        let path = astar(state.clone(), &SearchBudget::default(), &mut ())
+            .outcome
+            .into_path()
+            .expect("Deal should be solvable");
+        for mv in &path {
+            state = state.apply_move(mv.clone());
+        }
+        assert!(state.is_win());
+    }
+
+    #[test]
+    fn test_parse_round_trip() {
+        let mut deals = RUSSIAN_PRESETS
+            .iter()
+            .map(|(_, deal)| deal())
+            .chain([nearly_solved_russian(), ProletariatsPatience::deal(1)])
+            .collect::<Vec<_>>();
+        let state = russian();
+        let free = state
+            .legal_moves()
+            .into_iter()
+            .find(|mv| matches!(mv, Move::Free { .. }))
+            .expect("Deal should have a free move");
+        deals.push(state.apply_move(free));
+
+        for state in deals {
+            let text = state.to_string();
+            let parsed = text.parse::<ProletariatsPatience>().unwrap();
+            assert_eq!(parsed, state, "{text}");
+            assert_eq!(parsed.tableau, state.tableau);
+            assert_eq!(parsed.to_string(), text);
+        }
+    }
+
+    #[test]
+    fn test_parse_letters() {
+        // Hand-written deals can use letters for suits and left align the cards
+        let state = russian();
+        let rows = (0..6).map(|row| {
+            state
+                .tableau
+                .iter()
+                .map(|column| {
+                    let card = column.get(row).map_or(String::new(), |card| {
+                        let suit = match card.suit {
+                            Heart => 'H',
+                            Diamond => 'D',
+                            Club => 'c',
+                            Spade => 's',
+                        };
+                        format!("{}{suit}", card.rank)
+                    });
+                    format!("{card:<4}")
+                })
+                .collect::<String>()
+        });
+        let text = rows.collect::<Vec<_>>().join("\n");
+        assert_eq!(text.parse::<ProletariatsPatience>(), Ok(state));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let text = russian().to_string();
+        let parse = |text: &str| {
+            text.parse::<ProletariatsPatience>()
+                .err()
+                .map(|e| e.to_string())
+        };
+
+        let excluded = text.replacen("A\u{2665}", "5\u{2665}", 1);
+        assert_eq!(
+            parse(&excluded).as_deref(),
+            Some("5\u{2665} isn't part of this deck")
+        );
+
+        let duplicate = text.replacen("A\u{2665}", "K\u{2665}", 1);
+        assert_eq!(
+            parse(&duplicate).as_deref(),
+            Some("K\u{2665} was dealt more than once")
+        );
+
+        let missing = text.replacen("A\u{2665}", "  ", 1);
+        assert!(parse(&missing).is_some());
+        let rows = text.lines().count() - 1;
+        let kept = russian()
+            .tableau
+            .iter()
+            .map(|column| column.len().min(rows))
+            .sum::<usize>();
+        let short = text.lines().take(rows).collect::<Vec<_>>().join("\n");
+        assert_eq!(
+            parse(&short),
+            Some(format!("expected 36 cards, found {kept}"))
+        );
+
+        assert_eq!(
+            parse(" 7X").as_deref(),
+            Some("line 1, column 2: unknown suit X in 7X")
+        );
+        assert_eq!(
+            parse("\n 7H\n     8C").as_deref(),
+            Some("line 3, column 6: column 2 has a gap above 8C")
+        );
+        assert_eq!(
+            parse(" 7H\n 8C\t9D").as_deref(),
+            Some("line 2, column 5: the free cell is only on the first line")
+        );
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        for (name, deal) in RUSSIAN_PRESETS {
+            let mut state = deal();
+            for _ in 0..3 {
+                let mv = state.legal_moves()[0].clone();
+                state = state.apply_move(mv);
+            }
+            for state in [deal(), state] {
+                let json = serde_json::to_string(&state).unwrap();
+                let parsed = serde_json::from_str::<ProletariatsPatience>(&json).unwrap();
+                assert_eq!(parsed, state, "{name}");
+                assert_eq!(parsed.tableau, state.tableau, "{name}");
+                assert_eq!(parsed.history.to_vec(), state.history.to_vec(), "{name}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_json_schema() {
+        let mv = Move::Free {
+            card: Card {
+                rank: Ten,
+                suit: Heart,
+            },
+            from: 2,
+        };
+        assert_eq!(
+            serde_json::to_string(&mv).unwrap(),
+            r#"{"Free":{"card":{"suit":"Heart","rank":"Ten"},"from":2}}"#
+        );
+
+        let mut json = serde_json::to_value(russian()).unwrap();
+        json.as_object_mut().unwrap().remove("history");
+        let state = serde_json::from_value::<ProletariatsPatience>(json).unwrap();
+        assert_eq!(state, russian());
+
+        // Games are validated as they're read
+        let mut json = serde_json::to_value(russian()).unwrap();
+        json["tableau"][0][0] = json["tableau"][1][0].clone();
+        let err = serde_json::from_value::<ProletariatsPatience>(json).unwrap_err();
+        assert!(
+            err.to_string().ends_with("was dealt more than once"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_deal() {
+        let state = ProletariatsPatience::deal(42);
+        assert_eq!(state.tableau, ProletariatsPatience::deal(42).tableau);
+        assert_ne!(state, ProletariatsPatience::deal(43));
+        assert_eq!(state.validate_tableau(), Ok(()));
+        assert!(state.tableau.iter().all(|column| column.len() == 4));
+    }
 }
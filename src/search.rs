@@ -0,0 +1,507 @@
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::PEAK_ALLOC;
+
+/// How a search for a winning line ended
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SearchOutcome<A> {
+    /// A line of play that wins the game
+    Solved(Vec<A>),
+    /// Every state reachable from the deal was searched without finding a win.
+    /// Moves skipped by `State::is_redundant` only lead back to states that were already searched,
+    /// so they don't leave any out.
+    ProvenUnsolvable { states_explored: usize },
+    /// The search stopped before it could find a win or rule one out
+    Inconclusive { reason: StopReason },
+}
+
+impl<A> SearchOutcome<A> {
+    pub fn is_solved(&self) -> bool {
+        matches!(self, SearchOutcome::Solved(_))
+    }
+
+    /// The winning line, if one was found
+    pub fn into_path(self) -> Option<Vec<A>> {
+        match self {
+            SearchOutcome::Solved(path) => Some(path),
+            _ => None,
+        }
+    }
+}
+
+/// Why a search gave up without searching every reachable state
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum StopReason {
+    /// Some states were cut off by the depth limit
+    MaxDepth,
+    /// The time limit ran out
+    MaxSearchTime,
+    /// The search expanded as many states as it was allowed to
+    MaxNodes,
+    /// The program's memory use went over the limit
+    MaxMemory,
+    /// The search was stopped from outside, through its cancellation flag
+    Cancelled,
+    /// The solver skips states by design, like beam search dropping all but the best few
+    NotExhaustive,
+}
+
+impl StopReason {
+    /// Whether the whole search has to stop, rather than just the part of it that was cut off
+    pub fn ends_search(&self) -> bool {
+        !matches!(self, StopReason::MaxDepth | StopReason::NotExhaustive)
+    }
+}
+
+impl Display for StopReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            StopReason::MaxDepth => "the depth limit was reached",
+            StopReason::MaxSearchTime => "the time limit was reached",
+            StopReason::MaxNodes => "the node limit was reached",
+            StopReason::MaxMemory => "the memory limit was reached",
+            StopReason::Cancelled => "the search was cancelled",
+            StopReason::NotExhaustive => "the solver doesn't search every state",
+        };
+        write!(f, "{reason}")
+    }
+}
+
+/// Limits on how much work a search may do before it has to stop and return the best it has
+/// found so far. Every limit is optional, and the default budget is unlimited.
+#[derive(Clone, Debug, Default)]
+pub struct SearchBudget {
+    /// When the search has to stop
+    pub deadline: Option<Instant>,
+    /// Most states the search may expand
+    pub max_nodes: Option<u64>,
+    /// Most memory the search may allocate, in bytes
+    pub max_memory: Option<usize>,
+    /// Stops the search once it is set, for example by another thread
+    pub cancel: Option<Arc<AtomicBool>>,
+    /// Memory the program had allocated when the search started, which doesn't count against
+    /// `max_memory`. Searches record it themselves as they start.
+    pub base_memory: usize,
+}
+
+impl SearchBudget {
+    /// A budget that only limits time, starting from now
+    pub fn time_limit(time: Option<Duration>) -> Self {
+        Self {
+            deadline: time.and_then(|time| Instant::now().checked_add(time)),
+            base_memory: PEAK_ALLOC.current_usage(),
+            ..Self::default()
+        }
+    }
+
+    /// Why a search that has expanded `nodes_expanded` states has to stop, if it does
+    pub fn exhausted(&self, nodes_expanded: u64) -> Option<StopReason> {
+        if self
+            .cancel
+            .as_ref()
+            .is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+        {
+            return Some(StopReason::Cancelled);
+        }
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Some(StopReason::MaxSearchTime);
+        }
+        if self.max_nodes.is_some_and(|max| nodes_expanded >= max) {
+            return Some(StopReason::MaxNodes);
+        }
+        if self
+            .max_memory
+            .is_some_and(|max| PEAK_ALLOC.current_usage().saturating_sub(self.base_memory) > max)
+        {
+            return Some(StopReason::MaxMemory);
+        }
+        None
+    }
+}
+
+/// The outcome of a search, along with how much work it took
+#[derive(Clone, Debug)]
+pub struct SearchResult<A> {
+    pub outcome: SearchOutcome<A>,
+    pub stats: SearchStats,
+}
+
+/// Counters kept by the solvers as they search
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SearchStats {
+    /// States whose moves were generated
+    pub nodes_expanded: u64,
+    /// States created by making those moves
+    pub nodes_generated: u64,
+    /// Generated states that were skipped because they had already been searched
+    pub duplicates_pruned: u64,
+    /// Most moves from the deal to a state that was expanded
+    pub max_depth: usize,
+    pub elapsed: Duration,
+    /// Most memory the whole program had allocated at once during the search, in bytes, on top of
+    /// what it had allocated when the search started.
+    /// Memory that's freed again between reports is only counted if it set a new peak for the
+    /// whole program.
+    pub peak_memory: usize,
+}
+
+impl SearchStats {
+    /// Adds the counts from another part of the same search, like one of several worker threads
+    fn add(&mut self, other: &SearchStats) {
+        self.nodes_expanded += other.nodes_expanded;
+        self.nodes_generated += other.nodes_generated;
+        self.duplicates_pruned += other.duplicates_pruned;
+        self.max_depth = self.max_depth.max(other.max_depth);
+    }
+
+    /// Adds the statistics of a search that ran after this one, like the search for the next move
+    /// of a solver that picks one move at a time
+    pub fn add_search(&mut self, other: &SearchStats) {
+        self.add(other);
+        self.elapsed += other.elapsed;
+        self.peak_memory = self.peak_memory.max(other.peak_memory);
+    }
+
+    fn expand(&mut self, depth: usize) {
+        self.nodes_expanded += 1;
+        self.max_depth = self.max_depth.max(depth);
+    }
+}
+
+impl Display for SearchStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} states expanded ({} generated, {} duplicates), max depth {}, {:.2}s, peak {:.2}MB",
+            self.nodes_expanded,
+            self.nodes_generated,
+            self.duplicates_pruned,
+            self.max_depth,
+            self.elapsed.as_secs_f32(),
+            self.peak_memory as f32 / (1024. * 1024.)
+        )
+    }
+}
+
+/// Watches a search as it runs.
+/// Observers are called every `REPORT_INTERVAL` expanded states and once more when the search ends,
+/// so they should throttle anything slow (like drawing) themselves.
+pub trait SearchObserver: Send {
+    fn progress(&mut self, stats: &SearchStats);
+}
+
+/// For searches that nobody is watching
+impl SearchObserver for () {
+    fn progress(&mut self, _: &SearchStats) {}
+}
+
+/// Number of states expanded between calls to a `SearchObserver`
+pub const REPORT_INTERVAL: u64 = 1024;
+
+/// Counts the work a search does, and checks it against the search's budget
+pub trait Meter {
+    /// Records that the moves of a state `depth` moves from the deal were generated
+    fn expand(&mut self, depth: usize);
+    fn generate(&mut self);
+    fn prune_duplicate(&mut self);
+    /// Why the search has to stop, if its budget has run out
+    fn out_of_budget(&self) -> Option<StopReason>;
+}
+
+/// Keeps the statistics of one search and passes them on to its observer
+pub struct Tracker<'a> {
+    stats: SearchStats,
+    start: Instant,
+    /// Memory the program had allocated when the search started
+    base_memory: usize,
+    /// The program's peak memory use when the search started
+    base_peak: usize,
+    budget: SearchBudget,
+    observer: &'a mut dyn SearchObserver,
+}
+
+impl<'a> Tracker<'a> {
+    /// Starts the clock, and notes how much memory is in use so the search's own use can be told
+    /// apart from it. The allocator's peak is left alone, since other searches may be using it.
+    pub fn new(budget: &SearchBudget, observer: &'a mut dyn SearchObserver) -> Self {
+        let base_memory = PEAK_ALLOC.current_usage();
+        Self {
+            stats: SearchStats::default(),
+            start: Instant::now(),
+            base_memory,
+            base_peak: PEAK_ALLOC.peak_usage(),
+            budget: SearchBudget {
+                base_memory,
+                ..budget.clone()
+            },
+            observer,
+        }
+    }
+
+    /// Adds the counts kept separately by part of the search and reports the combined total
+    pub fn add(&mut self, stats: &SearchStats) {
+        self.stats.add(stats);
+        self.report();
+    }
+
+    /// Reports the final statistics and returns them with the outcome
+    pub fn finish<A>(self, outcome: SearchOutcome<A>) -> SearchResult<A> {
+        SearchResult {
+            outcome,
+            stats: self.into_stats(),
+        }
+    }
+
+    /// Reports the final statistics and returns them, for searches that only pick a move
+    pub fn into_stats(mut self) -> SearchStats {
+        self.report();
+        self.stats
+    }
+
+    fn report(&mut self) {
+        self.stats.elapsed = self.start.elapsed();
+        // A new peak for the whole program must have been reached during this search
+        let peak = PEAK_ALLOC.peak_usage();
+        let high = if peak > self.base_peak {
+            peak
+        } else {
+            PEAK_ALLOC.current_usage()
+        };
+        let used = high.saturating_sub(self.base_memory);
+        self.stats.peak_memory = self.stats.peak_memory.max(used);
+        self.observer.progress(&self.stats);
+    }
+}
+
+impl Meter for Tracker<'_> {
+    fn expand(&mut self, depth: usize) {
+        self.stats.expand(depth);
+        if self.stats.nodes_expanded.is_multiple_of(REPORT_INTERVAL) {
+            self.report();
+        }
+    }
+
+    fn generate(&mut self) {
+        self.stats.nodes_generated += 1;
+    }
+
+    fn prune_duplicate(&mut self) {
+        self.stats.duplicates_pruned += 1;
+    }
+
+    fn out_of_budget(&self) -> Option<StopReason> {
+        self.budget.exhausted(self.stats.nodes_expanded)
+    }
+}
+
+/// Statistics kept by one of several threads searching together, which are added to the shared
+/// `Tracker` every `REPORT_INTERVAL` expanded states.
+/// The node limit is checked against the shared total as of this thread's last flush, so the
+/// search can go over it by up to `REPORT_INTERVAL` states per thread.
+pub struct LocalStats<'t, 'a> {
+    stats: SearchStats,
+    budget: SearchBudget,
+    /// States expanded by the whole search, as of this thread's last flush
+    shared_nodes_expanded: u64,
+    tracker: &'t Mutex<Tracker<'a>>,
+}
+
+impl<'t, 'a> LocalStats<'t, 'a> {
+    pub fn new(tracker: &'t Mutex<Tracker<'a>>) -> Self {
+        let shared = tracker
+            .lock()
+            .expect("No worker should panic while holding the lock");
+        Self {
+            stats: SearchStats::default(),
+            budget: shared.budget.clone(),
+            shared_nodes_expanded: shared.stats.nodes_expanded,
+            tracker,
+        }
+    }
+
+    /// Adds the counts so far to the shared tracker
+    pub fn flush(&mut self) {
+        let mut tracker = self
+            .tracker
+            .lock()
+            .expect("No worker should panic while holding the lock");
+        tracker.add(&self.stats);
+        self.shared_nodes_expanded = tracker.stats.nodes_expanded;
+        self.stats = SearchStats::default();
+    }
+}
+
+impl Meter for LocalStats<'_, '_> {
+    fn expand(&mut self, depth: usize) {
+        self.stats.expand(depth);
+        if self.stats.nodes_expanded.is_multiple_of(REPORT_INTERVAL) {
+            self.flush();
+        }
+    }
+
+    fn generate(&mut self) {
+        self.stats.nodes_generated += 1;
+    }
+
+    fn prune_duplicate(&mut self) {
+        self.stats.duplicates_pruned += 1;
+    }
+
+    fn out_of_budget(&self) -> Option<StopReason> {
+        self.budget
+            .exhausted(self.shared_nodes_expanded + self.stats.nodes_expanded)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    use crate::search::{
+        LocalStats, Meter, SearchBudget, SearchObserver, SearchOutcome, SearchStats, StopReason,
+        Tracker, REPORT_INTERVAL,
+    };
+    use crate::PEAK_ALLOC;
+
+    /// Keeps every report it is sent
+    #[derive(Default)]
+    struct Recorder(Vec<SearchStats>);
+
+    impl SearchObserver for Recorder {
+        fn progress(&mut self, stats: &SearchStats) {
+            self.0.push(stats.clone());
+        }
+    }
+
+    #[test]
+    fn test_tracker_reports() {
+        let mut recorder = Recorder::default();
+        let mut tracker = Tracker::new(&SearchBudget::default(), &mut recorder);
+        for depth in 0..2 * REPORT_INTERVAL as usize + 1 {
+            tracker.expand(depth % 7);
+            tracker.generate();
+            tracker.generate();
+        }
+        tracker.prune_duplicate();
+        let result = tracker.finish(SearchOutcome::<()>::Solved(vec![]));
+
+        // Two reports along the way, then one at the end
+        assert_eq!(recorder.0.len(), 3);
+        assert_eq!(recorder.0[0].nodes_expanded, REPORT_INTERVAL);
+        assert_eq!(recorder.0.last(), Some(&result.stats));
+        assert_eq!(result.stats.nodes_expanded, 2 * REPORT_INTERVAL + 1);
+        assert_eq!(result.stats.nodes_generated, 4 * REPORT_INTERVAL + 2);
+        assert_eq!(result.stats.duplicates_pruned, 1);
+        assert_eq!(result.stats.max_depth, 6);
+    }
+
+    #[test]
+    fn test_peak_memory() {
+        const SIZE: usize = 64 * 1024 * 1024;
+        let peak_before = PEAK_ALLOC.peak_usage();
+        let observer = &mut ();
+        let tracker = Tracker::new(&SearchBudget::default(), observer);
+        // Starting a search doesn't lower the peak that other searches may be watching
+        assert!(PEAK_ALLOC.peak_usage() >= peak_before);
+
+        // Other tests allocate and free memory at the same time, so allow for some of it
+        let allocated = vec![1u8; SIZE];
+        let stats = tracker.into_stats();
+        assert!(stats.peak_memory >= SIZE / 2, "{stats}");
+        drop(allocated);
+    }
+
+    #[test]
+    fn test_local_stats() {
+        let mut recorder = Recorder::default();
+        let tracker = Mutex::new(Tracker::new(&SearchBudget::default(), &mut recorder));
+        let mut first = LocalStats::new(&tracker);
+        let mut second = LocalStats::new(&tracker);
+        first.expand(3);
+        second.expand(5);
+        second.prune_duplicate();
+        first.flush();
+        second.flush();
+
+        let stats = tracker
+            .into_inner()
+            .unwrap()
+            .finish(SearchOutcome::<()>::Solved(vec![]))
+            .stats;
+        assert_eq!(stats.nodes_expanded, 2);
+        assert_eq!(stats.duplicates_pruned, 1);
+        assert_eq!(stats.max_depth, 5);
+        // Each flush is reported as well as the end
+        assert_eq!(recorder.0.len(), 3);
+    }
+
+    #[test]
+    fn test_budget() {
+        assert_eq!(SearchBudget::default().exhausted(u64::MAX), None);
+
+        let budget = SearchBudget {
+            max_nodes: Some(10),
+            ..SearchBudget::time_limit(Some(Duration::from_secs(3600)))
+        };
+        assert_eq!(budget.exhausted(9), None);
+        assert_eq!(budget.exhausted(10), Some(StopReason::MaxNodes));
+
+        let budget = SearchBudget {
+            deadline: Some(Instant::now()),
+            ..SearchBudget::default()
+        };
+        assert_eq!(budget.exhausted(0), Some(StopReason::MaxSearchTime));
+
+        let budget = SearchBudget {
+            max_memory: Some(0),
+            ..SearchBudget::default()
+        };
+        assert_eq!(budget.exhausted(0), Some(StopReason::MaxMemory));
+        // Memory that was already in use when the search started doesn't count
+        let budget = SearchBudget {
+            max_memory: Some(1024 * 1024),
+            ..SearchBudget::time_limit(None)
+        };
+        assert_eq!(budget.exhausted(0), None);
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let budget = SearchBudget {
+            cancel: Some(cancel.clone()),
+            ..SearchBudget::default()
+        };
+        assert_eq!(budget.exhausted(0), None);
+        cancel.store(true, Ordering::Relaxed);
+        assert_eq!(budget.exhausted(0), Some(StopReason::Cancelled));
+    }
+
+    #[test]
+    fn test_local_stats_budget() {
+        let budget = SearchBudget {
+            max_nodes: Some(REPORT_INTERVAL + 1),
+            ..SearchBudget::default()
+        };
+        let observer = &mut ();
+        let tracker = Mutex::new(Tracker::new(&budget, observer));
+        let mut first = LocalStats::new(&tracker);
+        let mut second = LocalStats::new(&tracker);
+        for depth in 0..REPORT_INTERVAL as usize {
+            first.expand(depth);
+        }
+        assert_eq!(first.out_of_budget(), None);
+        first.expand(0);
+        assert_eq!(first.out_of_budget(), Some(StopReason::MaxNodes));
+        first.flush();
+        // The second worker only sees the states expanded by the first once it flushes
+        assert_eq!(second.out_of_budget(), None);
+        second.flush();
+        assert_eq!(second.out_of_budget(), Some(StopReason::MaxNodes));
+    }
+}
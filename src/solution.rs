@@ -0,0 +1,144 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::{Game, MoveError, State};
+use crate::solver::Solver;
+
+/// A line of play found by a solver, saved so that it can be checked and replayed later.
+///
+/// # JSON
+/// An object with these fields:
+/// - `game`: `"fortune"`, `"russian"` or `"cribbage"`
+/// - `solver`: the solver's name as given to `--solver`, like `"idastar"`
+/// - `deal`: the starting position, in the game's own JSON format
+/// - `moves`: the moves in the order they're played, in the game's own JSON format
+/// - `won`: whether the moves win the game
+/// - `score`: `evaluate` of the position after the last move
+/// - `seconds`: how long the search took
+/// - `nodes_expanded`: how many states the solver expanded, or `null` if it doesn't count them
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Solution<S, A> {
+    pub game: Game,
+    pub solver: Solver,
+    pub deal: S,
+    pub moves: Vec<A>,
+    pub won: bool,
+    pub score: f32,
+    pub seconds: f64,
+    pub nodes_expanded: Option<u64>,
+}
+
+impl<S> Solution<S, <S as State>::Action>
+where
+    S: State + Clone,
+{
+    /// Records a line, playing it out to find the final score
+    pub fn new(
+        game: Game,
+        solver: Solver,
+        deal: S,
+        moves: Vec<<S as State>::Action>,
+        elapsed: Duration,
+        nodes_expanded: Option<u64>,
+    ) -> Self {
+        let final_state = moves.iter().fold(deal.clone(), |state, mv| state.act(mv));
+        Self {
+            game,
+            solver,
+            won: final_state
+                .end_status()
+                .is_some_and(|end_state| end_state.is_win()),
+            score: final_state.evaluate(false),
+            deal,
+            moves,
+            seconds: elapsed.as_secs_f64(),
+            nodes_expanded,
+        }
+    }
+
+    /// Plays the moves from the deal, checking that each one is one of the `possible_actions`.
+    /// Returns every position along the way, starting with the deal, or the index of the first
+    /// illegal move and why it can't be made.
+    pub fn positions(&self) -> Result<Vec<S>, (usize, MoveError)>
+    where
+        <S as State>::Action: PartialEq,
+    {
+        let mut positions = vec![self.deal.clone()];
+        for (idx, mv) in self.moves.iter().enumerate() {
+            let state = positions.last().expect("Positions start with the deal");
+            if !state.possible_actions().contains(mv) {
+                // `try_act` can usually say why, but the move might also be legal
+                // and just never generated, like a manual build in Fortune's Foundation
+                let err = state.try_act(mv).err().unwrap_or(MoveError::NotAvailable);
+                return Err((idx, err));
+            }
+            positions.push(state.act(mv));
+        }
+        Ok(positions)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::astar::astar;
+    use crate::fortune::FortunesFoundation;
+    use crate::game::{Game, MoveError, State};
+    use crate::search::SearchBudget;
+    use crate::solution::Solution;
+    use crate::solver::Solver;
+    use crate::states::fortune_1;
+    use crate::testing::nearly_sorted_fortune;
+
+    #[test]
+    fn test_round_trip() {
+        let deal = nearly_sorted_fortune();
+        let moves = astar(deal.clone(), &SearchBudget::default(), &mut ())
+            .outcome
+            .into_path()
+            .expect("Deal should be solvable");
+        let solution = Solution::new(
+            Game::Fortune,
+            Solver::Astar,
+            deal.clone(),
+            moves,
+            Duration::from_millis(1500),
+            None,
+        );
+        assert!(solution.won);
+
+        let json = serde_json::to_string(&solution).unwrap();
+        assert!(json.starts_with(r#"{"game":"fortune","solver":"astar","deal":{"#));
+        let parsed: Solution<FortunesFoundation, _> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.deal, deal);
+        assert_eq!(parsed.moves, solution.moves);
+        assert_eq!(parsed.seconds, 1.5);
+
+        let positions = parsed.positions().unwrap();
+        assert_eq!(positions.len(), parsed.moves.len() + 1);
+        assert!(positions.last().unwrap().is_win());
+    }
+
+    #[test]
+    fn test_illegal_move() {
+        let deal = fortune_1();
+        let mv = deal.possible_actions()[0];
+        // Playing the same move twice can't work, since the card has already moved
+        let solution = Solution {
+            game: Game::Fortune,
+            solver: Solver::Dfs,
+            deal,
+            moves: vec![mv, mv],
+            won: false,
+            score: 0.0,
+            seconds: 0.0,
+            nodes_expanded: None,
+        };
+        assert!(matches!(
+            solution.positions(),
+            Err((1, MoveError::NotOnTop { .. }))
+        ));
+    }
+}
@@ -0,0 +1,32 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// The solvers that can be picked, as named on the command line and in saved solutions
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Solver {
+    /// Minimax, one move at a time
+    Minimax,
+    /// Minimax with the root moves split between threads, one move at a time
+    ParallelMinimax,
+    /// Monte Carlo tree search, one move at a time
+    Mcts,
+    Dfs,
+    DfsInPlace,
+    ParallelDfs,
+    /// Repeated DFS, lowering the depth limit until no shorter solution is found
+    OptimalDfs,
+    Astar,
+    Idastar,
+    Beam,
+}
+
+impl Solver {
+    /// Solvers that pick one move at a time, rather than searching for a whole line
+    pub fn is_per_move(&self) -> bool {
+        matches!(
+            self,
+            Solver::Minimax | Solver::ParallelMinimax | Solver::Mcts
+        )
+    }
+}
@@ -10,6 +10,24 @@ use crate::game::Rank::{
 use crate::game::Suit::{Club, Diamond, Heart, Spade};
 use crate::russian::ProletariatsPatience;
 
+/// Named deals, so they can be picked from the command line
+pub type Presets<S> = &'static [(&'static str, fn() -> S)];
+
+pub const CRIBBAGE_PRESETS: Presets<CribbageSolitaire> = &[("state_0001", state_0001)];
+pub const RUSSIAN_PRESETS: Presets<ProletariatsPatience> = &[
+    ("russian", russian),
+    ("russian_2", russian_2),
+    ("russian_3", russian_3),
+];
+pub const FORTUNE_PRESETS: Presets<FortunesFoundation> = &[
+    ("fortune_1", fortune_1),
+    ("fortune_2", fortune_2),
+    ("fortune_3", fortune_3),
+    ("fortune_4", fortune_4),
+    ("fortune_5", fortune_5),
+    ("fortune_6", fortune_6),
+];
+
 pub fn state_0001() -> CribbageSolitaire {
     // TODO: correct the suits
     CribbageSolitaire::new([
@@ -113,7 +131,7 @@ pub fn state_0001() -> CribbageSolitaire {
                 rank: King,
             },
             Card {
-                suit: Club,
+                suit: Diamond,
                 rank: Ace,
             },
             Card {
@@ -398,7 +416,7 @@ pub fn russian() -> ProletariatsPatience {
         ],
     ]);
 
-    state.validate_tableau();
+    state.validate_tableau().expect("Deal should be valid");
     state
 }
 
@@ -570,7 +588,7 @@ pub fn russian_2() -> ProletariatsPatience {
         ],
     ]);
 
-    state.validate_tableau();
+    state.validate_tableau().expect("Deal should be valid");
     state
 }
 
@@ -742,7 +760,7 @@ pub fn russian_3() -> ProletariatsPatience {
         ],
     ]);
 
-    state.validate_tableau();
+    state.validate_tableau().expect("Deal should be valid");
     state
 }
 
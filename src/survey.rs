@@ -0,0 +1,314 @@
+use std::fmt::Write;
+
+use serde::Serialize;
+
+use crate::game::Game;
+use crate::search::{SearchOutcome, StopReason};
+use crate::solver::Solver;
+
+/// What a solver found for one deal
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    Solved,
+    /// The solver searched every reachable position without finding a win
+    Unsolvable,
+    /// The search ran out of time before it finished
+    TimedOut,
+    /// The search expanded as many states as it was allowed to before it finished
+    OutOfNodes,
+    /// The program went over its memory limit before the search finished
+    OutOfMemory,
+    /// The search was cancelled before it finished
+    Cancelled,
+    /// The solver stopped without a win, but it doesn't search every position
+    /// (or was limited in depth), so there may still be one
+    GaveUp,
+}
+
+impl<A> From<&SearchOutcome<A>> for Outcome {
+    fn from(outcome: &SearchOutcome<A>) -> Self {
+        match outcome {
+            SearchOutcome::Solved(_) => Outcome::Solved,
+            SearchOutcome::ProvenUnsolvable { .. } => Outcome::Unsolvable,
+            SearchOutcome::Inconclusive { reason } => match reason {
+                StopReason::MaxSearchTime => Outcome::TimedOut,
+                StopReason::MaxNodes => Outcome::OutOfNodes,
+                StopReason::MaxMemory => Outcome::OutOfMemory,
+                StopReason::Cancelled => Outcome::Cancelled,
+                StopReason::MaxDepth | StopReason::NotExhaustive => Outcome::GaveUp,
+            },
+        }
+    }
+}
+
+impl Outcome {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Outcome::Solved => "solved",
+            Outcome::Unsolvable => "unsolvable",
+            Outcome::TimedOut => "timed-out",
+            Outcome::OutOfNodes => "out-of-nodes",
+            Outcome::OutOfMemory => "out-of-memory",
+            Outcome::Cancelled => "cancelled",
+            Outcome::GaveUp => "gave-up",
+        }
+    }
+}
+
+/// Result of solving one seeded deal
+#[derive(Clone, Debug, Serialize)]
+pub struct DealReport {
+    pub seed: u64,
+    pub outcome: Outcome,
+    /// Length of the solution, if one was found
+    pub moves: Option<usize>,
+    /// States expanded by the solver, if it counts them
+    pub nodes: Option<u64>,
+    pub seconds: f64,
+}
+
+/// Results of running one solver over a range of seeded deals
+#[derive(Clone, Debug, Serialize)]
+pub struct Survey {
+    pub game: Game,
+    pub solver: Solver,
+    pub deals: Vec<DealReport>,
+}
+
+impl Survey {
+    /// One line per deal, with a header
+    pub fn to_csv(&self) -> String {
+        let mut csv = "seed,outcome,moves,nodes,seconds\n".to_owned();
+        for deal in &self.deals {
+            let optional = |value: Option<String>| value.unwrap_or_default();
+            writeln!(
+                csv,
+                "{},{},{},{},{:.3}",
+                deal.seed,
+                deal.outcome.name(),
+                optional(deal.moves.map(|moves| moves.to_string())),
+                optional(deal.nodes.map(|nodes| nodes.to_string())),
+                deal.seconds
+            )
+            .expect("Writing to a string can't fail");
+        }
+        csv
+    }
+
+    /// How many deals had each outcome, then statistics and histograms for the solved deals
+    pub fn summary(&self) -> String {
+        let mut summary = String::new();
+        let total = self.deals.len();
+        writeln!(
+            summary,
+            "{total} deals of {:?} with {:?}",
+            self.game, self.solver
+        )
+        .unwrap();
+        for outcome in [
+            Outcome::Solved,
+            Outcome::Unsolvable,
+            Outcome::TimedOut,
+            Outcome::OutOfNodes,
+            Outcome::OutOfMemory,
+            Outcome::Cancelled,
+            Outcome::GaveUp,
+        ] {
+            let count = self
+                .deals
+                .iter()
+                .filter(|deal| deal.outcome == outcome)
+                .count();
+            let percent = 100.0 * count as f64 / total.max(1) as f64;
+            writeln!(
+                summary,
+                "{:>13}: {count:>6} ({percent:.1}%)",
+                outcome.name()
+            )
+            .unwrap();
+        }
+
+        let solved = self
+            .deals
+            .iter()
+            .filter(|deal| deal.outcome == Outcome::Solved)
+            .collect::<Vec<_>>();
+        if !solved.is_empty() {
+            let lengths = solved
+                .iter()
+                .filter_map(|deal| deal.moves.map(|moves| moves as f64))
+                .collect::<Vec<_>>();
+            let times = solved.iter().map(|deal| deal.seconds).collect::<Vec<_>>();
+            for (name, values) in [("Solution length", lengths), ("Seconds to solve", times)] {
+                writeln!(summary, "\n{name}: {}", Stats::of(&values)).unwrap();
+                summary.push_str(&histogram(&values, 10, 40));
+            }
+        }
+        let all_times = self.deals.iter().map(|deal| deal.seconds).sum::<f64>();
+        writeln!(summary, "\nTotal time: {all_times:.2}s").unwrap();
+        summary
+    }
+}
+
+struct Stats {
+    min: f64,
+    median: f64,
+    mean: f64,
+    max: f64,
+}
+
+impl Stats {
+    /// `values` must not be empty
+    fn of(values: &[f64]) -> Self {
+        let mut sorted = values.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        Self {
+            min: sorted[0],
+            median: sorted[sorted.len() / 2],
+            mean: sorted.iter().sum::<f64>() / sorted.len() as f64,
+            max: sorted[sorted.len() - 1],
+        }
+    }
+}
+
+impl std::fmt::Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "min {:.2}, median {:.2}, mean {:.2}, max {:.2}",
+            self.min, self.median, self.mean, self.max
+        )
+    }
+}
+
+/// Text histogram with up to `bins` equal ranges of values, with the largest bar `width` wide
+fn histogram(values: &[f64], bins: usize, width: usize) -> String {
+    let stats = Stats::of(values);
+    let range = stats.max - stats.min;
+    // Every value is the same, so there's only one bar to draw
+    let bins = if range == 0.0 { 1 } else { bins };
+    let mut counts = vec![0; bins];
+    for value in values {
+        let bin = ((value - stats.min) / range * bins as f64) as usize;
+        counts[bin.min(bins - 1)] += 1;
+    }
+
+    let tallest = counts.iter().copied().max().unwrap_or(0).max(1);
+    let mut histogram = String::new();
+    for (bin, count) in counts.into_iter().enumerate() {
+        let start = stats.min + range * bin as f64 / bins as f64;
+        let end = stats.min + range * (bin + 1) as f64 / bins as f64;
+        let bar = "#".repeat((count * width).div_ceil(tallest));
+        writeln!(histogram, "{start:>9.2} - {end:<9.2} {count:>6} {bar}").unwrap();
+    }
+    histogram
+}
+
+#[cfg(test)]
+mod test {
+    use crate::game::Game;
+    use crate::search::{SearchOutcome, StopReason};
+    use crate::solver::Solver;
+    use crate::survey::{histogram, DealReport, Outcome, Survey};
+
+    fn report(seed: u64, outcome: Outcome, moves: Option<usize>, seconds: f64) -> DealReport {
+        DealReport {
+            seed,
+            outcome,
+            moves,
+            nodes: None,
+            seconds,
+        }
+    }
+
+    #[test]
+    fn test_from_search_outcome() {
+        let outcome = |reason| Outcome::from(&SearchOutcome::<()>::Inconclusive { reason });
+        assert_eq!(
+            Outcome::from(&SearchOutcome::Solved(vec![()])),
+            Outcome::Solved
+        );
+        assert_eq!(
+            Outcome::from(&SearchOutcome::<()>::ProvenUnsolvable { states_explored: 5 }),
+            Outcome::Unsolvable
+        );
+        assert_eq!(outcome(StopReason::MaxSearchTime), Outcome::TimedOut);
+        assert_eq!(outcome(StopReason::MaxNodes), Outcome::OutOfNodes);
+        assert_eq!(outcome(StopReason::MaxMemory), Outcome::OutOfMemory);
+        assert_eq!(outcome(StopReason::Cancelled), Outcome::Cancelled);
+        assert_eq!(outcome(StopReason::MaxDepth), Outcome::GaveUp);
+        assert_eq!(outcome(StopReason::NotExhaustive), Outcome::GaveUp);
+    }
+
+    #[test]
+    fn test_csv() {
+        let survey = Survey {
+            game: Game::Russian,
+            solver: Solver::Astar,
+            deals: vec![
+                report(1, Outcome::Solved, Some(40), 0.5),
+                report(2, Outcome::TimedOut, None, 10.0),
+                report(3, Outcome::OutOfNodes, None, 2.0),
+            ],
+        };
+        assert_eq!(
+            survey.to_csv(),
+            "seed,outcome,moves,nodes,seconds\n1,solved,40,,0.500\n2,timed-out,,,10.000\n\
+             3,out-of-nodes,,,2.000\n"
+        );
+    }
+
+    #[test]
+    fn test_summary() {
+        let survey = Survey {
+            game: Game::Russian,
+            solver: Solver::Astar,
+            deals: vec![
+                report(1, Outcome::Solved, Some(30), 0.5),
+                report(2, Outcome::Solved, Some(50), 1.5),
+                report(3, Outcome::Unsolvable, None, 2.0),
+                report(4, Outcome::GaveUp, None, 0.1),
+            ],
+        };
+        let summary = survey.summary();
+        assert!(summary.contains("solved:      2 (50.0%)"), "{summary}");
+        assert!(summary.contains("unsolvable:      1 (25.0%)"), "{summary}");
+        assert!(summary.contains("Solution length: min 30.00, median 50.00, mean 40.00, max 50.00"));
+        assert!(summary.contains("Total time: 4.10s"));
+    }
+
+    #[test]
+    fn test_summary_none_solved() {
+        // The total time still counts when no deal was solved
+        let survey = Survey {
+            game: Game::Russian,
+            solver: Solver::Astar,
+            deals: vec![
+                report(1, Outcome::TimedOut, None, 10.0),
+                report(2, Outcome::Unsolvable, None, 2.5),
+            ],
+        };
+        let summary = survey.summary();
+        assert!(summary.contains("solved:      0 (0.0%)"), "{summary}");
+        assert!(!summary.contains("Solution length"), "{summary}");
+        assert!(summary.contains("Total time: 12.50s"), "{summary}");
+    }
+
+    #[test]
+    fn test_histogram() {
+        let lines = histogram(&[1.0, 2.0, 2.0, 4.0, 5.0], 4, 10)
+            .lines()
+            .map(|line| {
+                line.split_whitespace()
+                    .skip(3)
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(lines, ["1 #####", "2 ##########", "0", "2 ##########"]);
+
+        // All the same value
+        assert_eq!(histogram(&[3.0, 3.0], 10, 5).lines().count(), 1);
+    }
+}
@@ -0,0 +1,263 @@
+//! Small games and deals shared by the solver tests
+
+use std::hash::{Hash, Hasher};
+
+use strum::IntoEnumIterator;
+
+use crate::fortune;
+use crate::fortune::{FortunesFoundation, MajorArcana, TarotCard};
+use crate::game::{Card, EndState, MoveError, Rank, Reversible, State, Suit};
+use crate::game::Rank::{Ace, Eight, Jack, King, Nine, Queen, Seven, Six, Ten};
+use crate::game::Suit::{Club, Diamond, Heart, Spade};
+use crate::russian::ProletariatsPatience;
+
+/// Walk along a number line from 0 to 10 in steps of +1, +3 or -1.
+/// Overshooting 10 loses, and the shortest win takes 4 moves.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct NumberLine(pub i32);
+
+impl State for NumberLine {
+    type Action = i32;
+
+    fn end_status(&self) -> Option<EndState> {
+        match self.0 {
+            10 => Some(EndState::Win),
+            x if x > 10 => Some(EndState::Loss),
+            _ => None,
+        }
+    }
+
+    fn possible_actions(&self) -> Vec<i32> {
+        vec![-1, 1, 3]
+    }
+
+    fn act(&self, action: &i32) -> Self {
+        NumberLine(self.0 + action)
+    }
+
+    fn try_act(&self, action: &i32) -> Result<Self, MoveError> {
+        if self.end_status().is_some() {
+            return Err(MoveError::GameOver);
+        }
+        if !self.possible_actions().contains(action) {
+            return Err(MoveError::NotAvailable);
+        }
+        Ok(self.act(action))
+    }
+
+    fn evaluate(&self, _: bool) -> f32 {
+        self.0 as f32
+    }
+
+    fn cost_to_go(&self) -> usize {
+        ((10 - self.0).max(0) as usize).div_ceil(3)
+    }
+}
+
+/// Count up from 0 in steps of +1 or +2, where reaching 5 or more loses.
+/// It can never be won, and there are only 7 reachable states.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct DeadEnd(pub u8);
+
+impl State for DeadEnd {
+    type Action = u8;
+
+    fn end_status(&self) -> Option<EndState> {
+        (self.0 >= 5).then_some(EndState::Loss)
+    }
+
+    fn possible_actions(&self) -> Vec<u8> {
+        vec![1, 2]
+    }
+
+    fn act(&self, action: &u8) -> Self {
+        DeadEnd(self.0 + action)
+    }
+
+    fn try_act(&self, action: &u8) -> Result<Self, MoveError> {
+        if self.end_status().is_some() {
+            return Err(MoveError::GameOver);
+        }
+        if !self.possible_actions().contains(action) {
+            return Err(MoveError::NotAvailable);
+        }
+        Ok(self.act(action))
+    }
+
+    fn evaluate(&self, _: bool) -> f32 {
+        self.0 as f32
+    }
+}
+
+/// A game with the given number of moves, none of which change anything.
+/// A search can never get anywhere from it, since every move leads back to where it started.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Stuck(pub usize);
+
+impl State for Stuck {
+    type Action = ();
+
+    fn end_status(&self) -> Option<EndState> {
+        None
+    }
+
+    fn possible_actions(&self) -> Vec<()> {
+        vec![(); self.0]
+    }
+
+    fn act(&self, _: &()) -> Self {
+        self.clone()
+    }
+
+    fn try_act(&self, action: &()) -> Result<Self, MoveError> {
+        if self.0 == 0 {
+            return Err(MoveError::NotAvailable);
+        }
+        Ok(self.act(action))
+    }
+
+    fn evaluate(&self, _: bool) -> f32 {
+        0.
+    }
+}
+
+impl Reversible for Stuck {
+    type Undo = ();
+
+    fn apply_in_place(&mut self, _: &()) {}
+
+    fn undo(&mut self, _: ()) {}
+}
+
+/// A game with no moves whose hash is the same whatever it holds, for checking that hash
+/// collisions don't make searches mix up different states
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Collides(pub u8);
+
+impl Hash for Collides {
+    fn hash<H: Hasher>(&self, _: &mut H) {}
+}
+
+impl State for Collides {
+    type Action = ();
+
+    fn end_status(&self) -> Option<EndState> {
+        Some(EndState::Loss)
+    }
+
+    fn possible_actions(&self) -> Vec<()> {
+        Vec::new()
+    }
+
+    fn act(&self, _: &()) -> Self {
+        self.clone()
+    }
+
+    fn try_act(&self, _: &()) -> Result<Self, MoveError> {
+        Err(MoveError::NotAvailable)
+    }
+
+    fn evaluate(&self, _: bool) -> f32 {
+        0.
+    }
+}
+
+fn card(rank: Rank, suit: Suit) -> Card {
+    Card { rank, suit }
+}
+
+/// A solved deal, except that the 6s of hearts and diamonds were moved onto the empty column.
+/// The shortest win takes 2 moves.
+pub fn nearly_solved_russian() -> ProletariatsPatience {
+    ProletariatsPatience::new([
+        vec![
+            card(Ten, Heart),
+            card(Nine, Spade),
+            card(Eight, Heart),
+            card(Seven, Spade),
+        ],
+        vec![
+            card(Ten, Spade),
+            card(Nine, Heart),
+            card(Eight, Spade),
+            card(Seven, Heart),
+            card(Six, Spade),
+        ],
+        vec![
+            card(Ten, Diamond),
+            card(Nine, Club),
+            card(Eight, Diamond),
+            card(Seven, Club),
+        ],
+        vec![
+            card(Ten, Club),
+            card(Nine, Diamond),
+            card(Eight, Club),
+            card(Seven, Diamond),
+            card(Six, Club),
+        ],
+        vec![
+            card(Ace, Heart),
+            card(King, Heart),
+            card(Queen, Heart),
+            card(Jack, Heart),
+        ],
+        vec![
+            card(Ace, Spade),
+            card(King, Spade),
+            card(Queen, Spade),
+            card(Jack, Spade),
+        ],
+        vec![
+            card(Ace, Diamond),
+            card(King, Diamond),
+            card(Queen, Diamond),
+            card(Jack, Diamond),
+        ],
+        vec![
+            card(Ace, Club),
+            card(King, Club),
+            card(Queen, Club),
+            card(Jack, Club),
+        ],
+        vec![card(Six, Diamond), card(Six, Heart)],
+    ])
+}
+
+/// A deal where every column is already in building order, so it is won as soon as it is dealt
+pub fn sorted_fortune() -> FortunesFoundation {
+    FortunesFoundation::new(sorted_tableau())
+}
+
+/// Like `sorted_fortune`, except that the 2 and 3 of wands are swapped, which blocks the wands.
+/// The shortest win takes 1 move: the 3 to an empty column, after which everything builds.
+pub fn nearly_sorted_fortune() -> FortunesFoundation {
+    let mut tableau = sorted_tableau();
+    let len = tableau[0].len();
+    tableau[0].swap(len - 2, len - 1);
+
+    FortunesFoundation::new(tableau)
+}
+
+fn sorted_tableau() -> [Vec<TarotCard>; 11] {
+    let mut tableau: [Vec<TarotCard>; 11] = Default::default();
+    for (column, suit) in tableau.iter_mut().zip([
+        fortune::Suit::Wands,
+        fortune::Suit::Swords,
+        fortune::Suit::Cups,
+        fortune::Suit::Pentacles,
+    ]) {
+        // Ace is already on the foundation, so the column runs from King down to 2
+        *column = Rank::iter()
+            .skip(1)
+            .rev()
+            .map(|rank| TarotCard::Minor(fortune::Card { rank, suit }))
+            .collect();
+    }
+    tableau[4] = (0..=21)
+        .rev()
+        .map(|rank| TarotCard::Major(MajorArcana { rank }))
+        .collect();
+
+    tableau
+}